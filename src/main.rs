@@ -1,59 +1,171 @@
-use clap::Parser;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers},
     execute,
+    style::Print,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use rand::Rng;
+use rand::{
+    Rng, SeedableRng,
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+};
 use ratatui::{
     Frame, Terminal,
     backend::{Backend, CrosstermBackend},
     layout::{Constraint, Layout},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Axis, Block, Borders, Cell, Chart, Dataset, GraphType, Paragraph, Row, Table},
+    widgets::{
+        Axis, BarChart, Block, Borders, Cell, Chart, Dataset, GraphType, Paragraph, Row, Table,
+        Tabs,
+    },
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, VecDeque},
     env,
     error::Error,
     fs::{self, OpenOptions},
-    io::{self, Write},
+    io::{self, IsTerminal, Read, Write},
     path::PathBuf,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 // Application constants
 const MIN_TEXT_LENGTH: usize = 500;
+const MAX_LISTED_MISSED_WORDS: usize = 8;
+const COMMON_TYPO_COUNT: usize = 3;
+const SUMMARY_BIGRAM_COUNT: usize = 3;
+const ACCURACY_PASSAGE_LENGTH: usize = 200;
+const DEFAULT_WORD_COUNT: usize = 25;
+// Generous, so even sources with long words have enough to cut the passage from
+const WORDS_MODE_CHARS_PER_WORD: usize = 20;
+// Zen mode appends more text once fewer than this many characters are left
+const ZEN_REFILL_MARGIN: usize = 200;
 const WPM_UPDATE_INTERVAL_SECS: f64 = 1.0;
+// Longest --finish-word will wait for the last word, e.g. when stuck in correction mode
+const MAX_OVERTIME_SECS: u64 = 5;
+// Repeats of one key faster than this are taken as a held key under --debounce.
+// Auto-repeat typically runs at 25-30Hz (33-40ms apart), so this sits above it
+const DEBOUNCE_MS: u64 = 50;
+// --bell-on-error rings at most once per this many milliseconds
+const BELL_INTERVAL_MS: u64 = 250;
+// Bounds for --sample-interval; below a tenth of a second samples are mostly noise
+const MIN_SAMPLE_INTERVAL_SECS: f64 = 0.1;
+const MAX_SAMPLE_INTERVAL_SECS: f64 = 10.0;
 const INITIAL_WPM_DELAY_SECS: f64 = 2.0;
+const MAX_WPM_DELAY_SECS: f64 = 10.0;
 const CHARS_PER_WORD: f64 = 5.0;
+// Code is denser in symbols and indentation, so a "word" is counted as longer
+const CODE_CHARS_PER_WORD: f64 = 6.0;
 const MAX_WPM_CAP: f64 = 500.0;
-// Text scaling constants
-const ASSUMED_AVG_WPM: f64 = 150.0;
-const TEXT_BUFFER_MULTIPLIER: f64 = 10.0;
+// Upper bound for --max-wpm, which also sizes the passage
+const MAX_WPM_CAP_LIMIT: f64 = 10_000.0;
+// Headroom on top of a full run at --max-wpm when sizing the passage
+const TEXT_LENGTH_MARGIN: f64 = 1.5;
 const POLL_INTERVAL_MS: u64 = 50;
 const RENDER_INTERVAL_MS: u64 = 100;
-const VISIBLE_CHAR_LIMIT: usize = 300;
+const TWO_COLUMN_MIN_WIDTH: u16 = 160;
+// The first five physical keys of each row belong to the left hand
+const LEFT_HAND_KEYS_PER_ROW: usize = 5;
+const COLUMN_SPACING: u16 = 4;
+const LIVE_GRAPH_HEIGHT: u16 = 10;
+const PAUSE_OVERLAY_WIDTH: u16 = 24;
+// Below this the screens can't be laid out, so a resize hint is shown instead
+const MIN_TERMINAL_WIDTH: u16 = 40;
+const MIN_TERMINAL_HEIGHT: u16 = 10;
 const MIN_WORD_LENGTH: usize = 3;
+// Real home-row words are scarce, so --home-row tops the list up with made-up ones
+const MIN_HOME_ROW_WORDS: usize = 100;
+const TAB_WIDTH: usize = 4;
+const MAX_INJECTED_DIGITS: usize = 4;
+const TRAILING_PUNCTUATION: [char; 6] = [',', '.', ';', ':', '!', '?'];
+const WRAPPING_PUNCTUATION: [(char, char); 3] = [('(', ')'), ('"', '"'), ('\'', '\'')];
+const NO_REPEAT_WINDOW: usize = 3;
+const MIN_KEY_COVERAGE: usize = 3;
+// Fewest words a list needs for --text-source auto to pick it
+const MIN_AUTO_SOURCE_WORDS: usize = 500;
+// Fewest words --only-keys and --avoid-keys may leave before they're dropped
+const MIN_KEY_FILTER_WORDS: usize = 20;
 const HISTORY_FILENAME: &str = ".ratatype_history.csv";
+const HISTORY_JSONL_FILENAME: &str = ".ratatype_history.jsonl";
+const MAX_SAVED_TEXT_CHARS: usize = 5000;
+const PROGRESS_FILENAME: &str = ".ratatype_progress.json";
+const SETTINGS_FILENAME: &str = ".ratatype_settings.json";
+const BIGRAMS_FILENAME: &str = ".ratatype_bigrams.json";
+// The last text piped into ratatype
+const STDIN_FILENAME: &str = ".ratatype_stdin.txt";
+const MIN_BIGRAM_SAMPLES: u64 = 5;
+const DRILL_BIGRAM_COUNT: usize = 10;
+// Share of words drawn from the drill list; the rest keep the passage varied
+const DRILL_WORD_SHARE: f64 = 0.7;
+const WORD_REVIEWS_FILENAME: &str = ".ratatype_words.json";
+const DAILY_FILENAME: &str = ".ratatype_daily.json";
+// Words typed cleanly at ever longer intervals graduate out of review after this
+const MAX_REVIEW_INTERVAL_DAYS: u64 = 32;
+const MENU_DURATIONS: [&str; 5] = ["15", "30", "60", "120", "300"];
+const COMPARE_KEY_COUNT: usize = 5;
+const HISTORY_PAGE_ROWS: usize = 10;
+const MIN_FORECAST_RUNS: usize = 5;
+const MIN_FORECAST_R_SQUARED: f64 = 0.1;
+const MAX_FORECAST_DAYS: f64 = 365.0;
+const SECS_PER_DAY: f64 = 86400.0;
+const SHARE_CODE_PREFIX: &str = "ratatype:";
+const SHARE_KEY_COUNT: usize = 3;
+const CSV_HEADER: &str = "timestamp,duration_seconds,avg_wpm,peak_wpm,accuracy,characters_typed,errors,correction_mode,text_source,max_word_length,seed,true_peak_wpm,chars_per_word,mode,playlist_runs,word_accuracy,burst_wpm,reaction_ms,case,best_streak,drill,key_accuracy_passed";
+// Slowest and fastest keys listed in an --export-md or --report report
+const REPORT_KEY_COUNT: usize = 5;
+// Widest the --report WPM sparkline gets; longer tests are averaged down to fit
+const SPARKLINE_WIDTH: usize = 60;
+const SPARKLINE_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const KEYLOG_HEADER: &str = "elapsed_ms,target_char,typed_char,correct,response_ms";
+// Stand for Backspace, Ctrl+Backspace and Ctrl+Enter in the keylog, written as \b, \w and \s
+const KEYLOG_BACKSPACE: char = '\u{8}';
+const KEYLOG_DELETE_WORD: char = '\u{17}';
+const KEYLOG_SKIP: char = '\u{18}';
 const DICT_PATH: &str = "/usr/share/dict/words";
+#[cfg(feature = "broadcast")]
+const MAX_BROADCAST_REQUEST_BYTES: usize = 8192;
+#[cfg(feature = "url")]
+const URL_CACHE_DIRNAME: &str = ".ratatype_cache";
+// Exported heatmap geometry in pixels
+const SVG_KEY_PITCH: u32 = 46;
+const SVG_MARGIN: u32 = 20;
+const SVG_TITLE_HEIGHT: u32 = 32;
+// Placeholder pushed into user input for skipped characters (never matches a target)
+const SKIPPED_CHAR_MARKER: char = '\0';
 
 // Embedded word list
 const GOOGLE_10000_WORDS: &str = include_str!("../data/google-10000.txt");
 
 #[derive(Debug, Clone, PartialEq)]
 enum TextSource {
+    // Resolved to the first usable word source when the passage is generated
+    Auto,
     Google10k,
     SystemDict,
     Builtin,
     File(PathBuf),
+    // Prose from a file, typed as written (--file)
+    Passage(PathBuf),
 }
 
 impl std::str::FromStr for TextSource {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Accept the "file:<path>" form written to the history file
+        if let Some(path) = s.strip_prefix("file:") {
+            return Ok(TextSource::File(PathBuf::from(path)));
+        }
+        if let Some(path) = s.strip_prefix("passage:") {
+            return Ok(TextSource::Passage(PathBuf::from(path)));
+        }
+
         // Check if it's a file path first
         let path = PathBuf::from(s);
         if path.exists() && path.is_file() {
@@ -64,981 +176,5323 @@ impl std::str::FromStr for TextSource {
             "google" | "google10k" | "top10k" => Ok(TextSource::Google10k),
             "system" | "dict" | "dictionary" => Ok(TextSource::SystemDict),
             "builtin" | "built-in" | "samples" => Ok(TextSource::Builtin),
+            "auto" => Ok(TextSource::Auto),
             _ => Err(format!(
-                "Invalid text source '{}'. Valid options: google, system, builtin, or a path to a file",
+                "Invalid text source '{}'. Valid options: auto, google, system, builtin, or a path to a file",
                 s
             )),
         }
     }
 }
 
+impl TextSource {
+    fn default_chars_per_word(&self) -> f64 {
+        match self {
+            TextSource::File(_) => CODE_CHARS_PER_WORD,
+            _ => CHARS_PER_WORD,
+        }
+    }
+}
+
 impl std::fmt::Display for TextSource {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            TextSource::Auto => write!(f, "auto"),
             TextSource::Google10k => write!(f, "google"),
             TextSource::SystemDict => write!(f, "system"),
             TextSource::Builtin => write!(f, "builtin"),
             TextSource::File(path) => write!(f, "file:{}", path.display()),
+            TextSource::Passage(path) => write!(f, "passage:{}", path.display()),
         }
     }
 }
 
-#[derive(Parser)]
-#[command(name = "ratatype")]
-#[command(about = "A TUI-based typing test application")]
-#[command(version)]
-struct Args {
-    /// Duration of the typing test in seconds
-    #[arg(short, long, default_value_t = 30)]
-    duration: u64,
-
-    /// Require errors to be corrected before proceeding
-    #[arg(short = 'c', long, default_value_t = false)]
-    require_correction: bool,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HistoryFormat {
+    Csv,
+    Jsonl,
+}
 
-    /// Text source for typing test
-    #[arg(
-        short = 's',
-        long,
-        default_value = "google",
-        help = "Text source: google (top 10k words), system (/usr/share/dict/words), builtin (sample texts), or path to a code file"
-    )]
-    text_source: TextSource,
+impl std::str::FromStr for HistoryFormat {
+    type Err = String;
 
-    /// Maximum word length when using dictionary words
-    #[arg(short = 'm', long, default_value_t = 7, value_parser = validate_word_length)]
-    max_word_length: usize,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(HistoryFormat::Csv),
+            "jsonl" | "json" => Ok(HistoryFormat::Jsonl),
+            _ => Err(format!(
+                "Invalid history format '{}'. Valid options: csv, jsonl",
+                s
+            )),
+        }
+    }
 }
 
-fn validate_word_length(s: &str) -> Result<usize, String> {
-    let value: usize = s.parse().map_err(|_| "Must be a positive integer")?;
-    if value < MIN_WORD_LENGTH {
-        Err(format!("Word length must be at least {}", MIN_WORD_LENGTH))
-    } else if value > 20 {
-        Err("Word length must be 20 or less".to_string())
-    } else {
-        Ok(value)
+impl std::fmt::Display for HistoryFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryFormat::Csv => write!(f, "csv"),
+            HistoryFormat::Jsonl => write!(f, "jsonl"),
+        }
     }
 }
 
-#[derive(Debug)]
-struct TestHistory {
-    timestamp: u64,
-    duration_seconds: u64,
-    avg_wpm: f64,
-    peak_wpm: f64,
-    accuracy: f64,
-    characters_typed: usize,
-    errors: usize,
-    correction_mode: bool,
-    text_source: String,
-    max_word_length: usize,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TestMode {
+    Timed,
+    Accuracy,
+    // A fixed number of words, untimed (--words)
+    Words,
+    // No clock and no end to the text; over when you press Esc (--zen)
+    Zen,
 }
 
-#[derive(Debug, Clone)]
-struct KeyMetrics {
-    times: Vec<Duration>,
-    errors: usize,
-}
+impl std::str::FromStr for TestMode {
+    type Err = String;
 
-impl KeyMetrics {
-    fn new() -> Self {
-        Self {
-            times: Vec::new(),
-            errors: 0,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "timed" | "speed" => Ok(TestMode::Timed),
+            "accuracy" => Ok(TestMode::Accuracy),
+            "words" => Ok(TestMode::Words),
+            "zen" => Ok(TestMode::Zen),
+            _ => Err(format!(
+                "Invalid mode '{}'. Valid options: timed, accuracy, words, zen",
+                s
+            )),
         }
     }
+}
 
-    fn average_time(&self) -> Option<Duration> {
-        if self.times.is_empty() {
-            None
-        } else {
-            let total_nanos: u64 = self.times.iter().map(|d| d.as_nanos() as u64).sum();
-            Some(Duration::from_nanos(total_nanos / self.times.len() as u64))
+impl std::fmt::Display for TestMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestMode::Timed => write!(f, "timed"),
+            TestMode::Accuracy => write!(f, "accuracy"),
+            TestMode::Words => write!(f, "words"),
+            TestMode::Zen => write!(f, "zen"),
         }
     }
 }
 
-struct App {
-    target_text: String,
-    user_input: String,
-    current_position: usize,
-    start_time: Option<Instant>,
-    wpm_history: Vec<f64>,
-    wpm_data_points: Vec<(f64, f64)>, // (time, wpm) for graphing
-    test_duration: Duration,
-    is_finished: bool,
-    errors: usize,
-    total_keystrokes: usize,
-    last_wpm_update: Option<Instant>,
-    require_correction: bool,
-    correction_attempts: Vec<bool>, // Track which positions had errors
-    text_source: TextSource,
-    max_word_length: usize,
-    sample_texts: Vec<String>,
-    // Cache for performance
-    target_chars: Vec<char>,
-    // Key analytics tracking
-    key_metrics: HashMap<char, KeyMetrics>,
-    last_keystroke_time: Option<Instant>,
-    current_key_start_time: Option<Instant>,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Overtime {
+    // Stats stop at the deadline; the extra keystrokes only finish the word
+    Exclude,
+    Include,
 }
 
-impl App {
-    fn new(
-        duration_secs: u64,
-        require_correction: bool,
-        text_source: TextSource,
-        max_word_length: usize,
-    ) -> App {
-        let sample_texts = vec![
-            "The quick brown fox jumps over the lazy dog. This pangram contains every letter of the alphabet at least once.".to_string(),
-            "In a hole in the ground there lived a hobbit. Not a nasty, dirty, wet hole filled with the ends of worms and an oozy smell.".to_string(),
-            "To be or not to be, that is the question. Whether 'tis nobler in the mind to suffer the slings and arrows of outrageous fortune.".to_string(),
-            "It was the best of times, it was the worst of times, it was the age of wisdom, it was the age of foolishness and doubt.".to_string(),
-            "All human beings are born free and equal in dignity and rights. They are endowed with reason and conscience.".to_string(),
-            "The only way to do great work is to love what you do. If you haven't found it yet, keep looking and don't settle.".to_string(),
-            "Two things are infinite: the universe and human stupidity; and I'm not sure about the universe and its vast mysteries.".to_string(),
-            "In the midst of winter, I found there was, within me, an invincible summer that could not be defeated by any force.".to_string(),
-        ];
-
-        let mut app = App {
-            target_text: String::new(),
-            user_input: String::new(),
-            current_position: 0,
-            start_time: None,
-            wpm_history: Vec::new(),
-            wpm_data_points: Vec::new(),
-            test_duration: Duration::from_secs(duration_secs),
-            is_finished: false,
-            errors: 0,
-            total_keystrokes: 0,
-            last_wpm_update: None,
-            require_correction,
-            correction_attempts: Vec::new(),
-            text_source,
-            max_word_length,
-            sample_texts,
-            target_chars: Vec::new(),
-            key_metrics: HashMap::new(),
-            last_keystroke_time: None,
-            current_key_start_time: None,
-        };
+impl std::str::FromStr for Overtime {
+    type Err = String;
 
-        app.generate_text();
-        app.start_timing_current_key();
-        app
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exclude" => Ok(Overtime::Exclude),
+            "include" => Ok(Overtime::Include),
+            _ => Err(format!(
+                "Invalid overtime '{}'. Valid options: exclude, include",
+                s
+            )),
+        }
     }
+}
 
-    fn start_timing_current_key(&mut self) {
-        if self.current_position < self.target_chars.len() {
-            self.current_key_start_time = Some(Instant::now());
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorBehavior {
+    Advance,
+    Stay,
+}
+
+impl std::str::FromStr for ErrorBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "advance" => Ok(ErrorBehavior::Advance),
+            "stay" => Ok(ErrorBehavior::Stay),
+            _ => Err(format!(
+                "Invalid error behavior '{}'. Valid options: advance, stay",
+                s
+            )),
         }
     }
-    
-    fn is_code_mode(&self) -> bool {
-        matches!(self.text_source, TextSource::File(_))
-    }
-    
-    fn skip_leading_whitespace(&mut self) {
-        if !self.is_code_mode() {
-            return;
-        }
-        
-        // Skip leading spaces and tabs at the current position
-        while self.current_position < self.target_chars.len() {
-            let ch = self.target_chars[self.current_position];
-            if ch == ' ' || ch == '\t' {
-                self.current_position += 1;
-            } else {
-                break;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PassageCase {
+    Preserve,
+    Lower,
+    Title,
+}
+
+impl PassageCase {
+    fn apply(&self, text: &str) -> String {
+        match self {
+            PassageCase::Preserve => text.to_string(),
+            PassageCase::Lower => text.to_lowercase(),
+            PassageCase::Title => {
+                // Capitalize the first letter after any whitespace, leaving the rest alone
+                let mut at_word_start = true;
+                text.chars()
+                    .flat_map(|c| {
+                        let upper = at_word_start && c.is_alphabetic();
+                        at_word_start = c.is_whitespace();
+                        if upper {
+                            c.to_uppercase().collect::<Vec<_>>()
+                        } else {
+                            vec![c]
+                        }
+                    })
+                    .collect()
             }
         }
-        
-        // Ensure user_input matches the skipped position
-        while self.user_input.len() < self.current_position {
-            let ch = self.target_chars[self.user_input.len()];
-            self.user_input.push(ch);
+    }
+}
+
+impl std::str::FromStr for PassageCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "preserve" => Ok(PassageCase::Preserve),
+            "lower" => Ok(PassageCase::Lower),
+            "title" => Ok(PassageCase::Title),
+            _ => Err(format!(
+                "Invalid case '{}'. Valid options: preserve, lower, title",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for PassageCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PassageCase::Preserve => write!(f, "preserve"),
+            PassageCase::Lower => write!(f, "lower"),
+            PassageCase::Title => write!(f, "title"),
         }
     }
+}
 
-    fn calculate_required_text_length(&self) -> usize {
-        // Calculate characters needed based on test duration and expected typing speed
-        let test_duration = self.test_duration.as_secs_f64();
-        let words_per_sec = ASSUMED_AVG_WPM / 60.0;
-        let chars_needed =
-            (words_per_sec * CHARS_PER_WORD * test_duration * TEXT_BUFFER_MULTIPLIER) as usize;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnLayout {
+    One,
+    Two,
+    Auto,
+}
 
-        // For code mode, be more generous to ensure we don't run out
-        let multiplier = if self.is_code_mode() { 2.0 } else { 1.0 };
-        let adjusted_chars = (chars_needed as f64 * multiplier) as usize;
+impl std::str::FromStr for ColumnLayout {
+    type Err = String;
 
-        // Ensure we have at least the minimum length
-        adjusted_chars.max(MIN_TEXT_LENGTH)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "1" => Ok(ColumnLayout::One),
+            "2" => Ok(ColumnLayout::Two),
+            "auto" => Ok(ColumnLayout::Auto),
+            _ => Err(format!(
+                "Invalid column layout '{}'. Valid options: 1, 2, auto",
+                s
+            )),
+        }
     }
+}
 
-    fn generate_text(&mut self) {
-        let text = match &self.text_source {
-            TextSource::Google10k => self.generate_google10k_text(),
-            TextSource::SystemDict => self.generate_system_dict_text(),
-            TextSource::Builtin => self.generate_builtin_text(),
-            TextSource::File(path) => self.generate_file_text(path),
-        };
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Background {
+    Auto,
+    Dark,
+    Light,
+}
 
-        self.target_text = text;
-        // Cache character vector for performance and initialize correction_attempts
-        self.target_chars = self.target_text.chars().collect();
-        self.correction_attempts = vec![false; self.target_chars.len()];
-        
-        // Skip leading whitespace at the beginning for code mode
-        self.skip_leading_whitespace();
+impl Background {
+    fn resolve(self) -> Self {
+        if self != Background::Auto {
+            return self;
+        }
+        // Many terminals export COLFGBG as "fg;bg" with ANSI color numbers; 7 and 9-15 are light
+        match std::env::var("COLFGBG")
+            .ok()
+            .and_then(|value| value.rsplit(';').next()?.parse::<u8>().ok())
+        {
+            Some(7 | 9..=15) => Background::Light,
+            _ => Background::Dark,
+        }
     }
 
-    fn generate_builtin_text(&self) -> String {
-        let mut rng = rand::thread_rng();
-        let mut text = String::new();
-        let required_length = self.calculate_required_text_length();
+    // The colors below assume a resolved background; Auto renders as dark
 
-        // Generate enough text for the test duration
-        while text.len() < required_length {
-            let sample = &self.sample_texts[rng.gen_range(0..self.sample_texts.len())];
-            if !text.is_empty() {
-                text.push(' ');
-            }
-            text.push_str(sample);
+    fn text(self) -> Color {
+        match self {
+            Background::Light => Color::Black,
+            _ => Color::White,
         }
+    }
 
-        text
+    fn muted(self) -> Color {
+        match self {
+            Background::Light => Color::DarkGray,
+            _ => Color::Gray,
+        }
     }
 
-    fn generate_google10k_text(&self) -> String {
-        let words = self.load_google10k_words();
-        self.generate_word_text(&words)
+    fn pending(self) -> Color {
+        match self {
+            Background::Light => Color::Rgb(130, 130, 130),
+            _ => Color::DarkGray,
+        }
     }
 
-    fn generate_system_dict_text(&self) -> String {
-        match self.load_system_dict_words() {
-            Ok(words) => {
-                if words.is_empty() {
-                    return self.generate_builtin_text(); // Fallback
-                }
-                self.generate_word_text(&words)
+    fn faded(self) -> Color {
+        // Text pushed further back than pending, like the passage outside --focus-word
+        match self {
+            Background::Light => Color::Rgb(200, 200, 200),
+            _ => Color::Rgb(60, 60, 60),
+        }
+    }
+
+    fn highlight(self) -> Style {
+        // Cursor and selected rows: the text color as background
+        match self {
+            Background::Light => Style::default().fg(Color::White).bg(Color::Black),
+            _ => Style::default().fg(Color::Black).bg(Color::White),
+        }
+    }
+
+    fn unused_key(self) -> Color {
+        match self {
+            Background::Light => Color::Rgb(215, 215, 215),
+            _ => Color::DarkGray,
+        }
+    }
+}
+
+impl std::str::FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Background::Auto),
+            "dark" => Ok(Background::Dark),
+            "light" => Ok(Background::Light),
+            _ => Err(format!(
+                "Invalid background '{}'. Valid options: auto, dark, light",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(name = "ratatype")]
+#[command(about = "A TUI-based typing test application")]
+#[command(version)]
+#[command(group(
+    clap::ArgGroup::new("injection")
+        .args(["include_numbers", "include_punctuation"])
+        .multiple(true)
+))]
+struct Args {
+    /// Duration of the typing test in seconds
+    #[arg(short, long, default_value_t = 30)]
+    duration: u64,
+
+    /// Test mode: timed, accuracy (short untimed passage that must be completed), words (see --words) or zen (see --zen)
+    #[arg(long, default_value = "timed")]
+    mode: TestMode,
+
+    /// Type this many words instead of racing the clock; the test ends with the last word (default 25 with --mode words)
+    #[arg(long, value_name = "N", conflicts_with_all = ["mode", "learn"], value_parser = clap::value_parser!(u64).range(1..))]
+    words: Option<u64>,
+
+    /// Type with no timer and no end to the text until you press Esc, then see the summary
+    #[arg(long, default_value_t = false, conflicts_with_all = ["mode", "words", "duration", "learn"])]
+    zen: bool,
+
+    /// When time runs out mid-word, keep going until the word is done; its WPM can exclude (default) or include the overtime
+    #[arg(long, value_name = "OVERTIME", num_args = 0..=1, default_missing_value = "exclude")]
+    finish_word: Option<Overtime>,
+
+    /// Beginner lessons: an untimed passage with no WPM, and finger hints when you pause on a key
+    #[arg(long, default_value_t = false, conflicts_with = "mode")]
+    learn: bool,
+
+    /// Seconds to pause on a key before --learn shows which finger to use
+    #[arg(long, value_name = "SECONDS", default_value_t = 3, requires = "learn", value_parser = clap::value_parser!(u64).range(1..))]
+    hint_delay: u64,
+
+    /// Require errors to be corrected before proceeding
+    #[arg(short = 'c', long, default_value_t = false)]
+    require_correction: bool,
+
+    /// What a wrong character does without -c: advance past it, or stay until corrected (space skips the word)
+    #[arg(long, default_value = "advance")]
+    error_behavior: ErrorBehavior,
+
+    /// Ignore a key repeated within a few milliseconds unless the passage repeats it too
+    #[arg(long, default_value_t = false)]
+    debounce: bool,
+
+    /// Ring the terminal bell on a mistake (at most a few times a second)
+    #[arg(long, default_value_t = false)]
+    bell_on_error: bool,
+
+    /// Make a missing or extra space a hard error that must be corrected, even without -c
+    #[arg(long, default_value_t = false)]
+    strict_space: bool,
+
+    /// Text source for typing test
+    #[arg(
+        short = 's',
+        long,
+        default_value = "google",
+        help = "Text source: google (top 10k words), system (/usr/share/dict/words), builtin (sample texts), auto (first available of system, google, builtin), or path to a code file"
+    )]
+    text_source: TextSource,
+
+    /// Type the prose in this file as the passage, in place of --text-source
+    #[arg(long, value_name = "PATH", conflicts_with = "text_source")]
+    file: Option<PathBuf>,
+
+    /// Maximum word length when using dictionary words
+    #[arg(short = 'm', long, default_value_t = 7, value_parser = validate_word_length)]
+    max_word_length: usize,
+
+    /// Capitalization of the passage: preserve, lower (no Shift needed), or title
+    #[arg(long, default_value = "preserve")]
+    case: PassageCase,
+
+    /// Capitalize sentence starts, and this percentage of other words (default 25), to practice Shift
+    #[arg(long, value_name = "PERCENT", num_args = 0..=1, default_missing_value = "25", conflicts_with = "case", value_parser = clap::value_parser!(u32).range(1..=100))]
+    capitalize: Option<u32>,
+
+    /// Also report the share of words typed without any mistakes
+    #[arg(long, default_value_t = false)]
+    word_accuracy: bool,
+
+    /// Fail the run if this key's accuracy ends below the given percentage
+    #[arg(long, num_args = 2, value_names = ["KEY", "PERCENT"])]
+    require_key_accuracy: Option<Vec<String>>,
+
+    /// Favor words containing your slowest letter pairs from past tests
+    #[arg(long, default_value_t = false)]
+    drill_bigrams: bool,
+
+    /// Pick common words more often, the way they come up in real English (google text source only)
+    #[arg(long, default_value_t = false)]
+    frequency_weighted: bool,
+
+    /// Only use words containing at least one of these keys, to drill them (e.g. "qz")
+    #[arg(long, value_name = "KEYS")]
+    only_keys: Option<String>,
+
+    /// Leave out words containing any of these keys
+    #[arg(long, value_name = "KEYS")]
+    avoid_keys: Option<String>,
+
+    /// Build the passage from words you've missed before that are due for review
+    #[arg(long, default_value_t = false)]
+    review: bool,
+
+    /// Steer word choice so every letter appears a few times early in the passage
+    #[arg(long, default_value_t = false)]
+    cover_all_keys: bool,
+
+    /// Mix digit groups in between the words
+    #[arg(long, default_value_t = false)]
+    include_numbers: bool,
+
+    /// Add punctuation to some words: trailing marks like , . ? or wrapping quotes and brackets
+    #[arg(long, default_value_t = false)]
+    include_punctuation: bool,
+
+    /// Share of words that get a number or punctuation with --include-numbers/--include-punctuation
+    #[arg(long, value_name = "PERCENT", default_value_t = 15, requires = "injection", value_parser = clap::value_parser!(u32).range(1..=100))]
+    injection_rate: u32,
+
+    /// Use only words made of home-row letters (asdfghjkl on QWERTY)
+    #[arg(long, default_value_t = false, conflicts_with = "cover_all_keys")]
+    home_row: bool,
+
+    /// Characters counted as one word in WPM (default depends on the text source)
+    #[arg(long, value_parser = validate_chars_per_word)]
+    chars_per_word: Option<f64>,
+
+    /// Length in seconds of the window used for the burst WPM stat
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    burst_window: u64,
+
+    /// Seconds between WPM samples for the graph; smaller is smoother for short tests
+    #[arg(long, value_name = "SECONDS", default_value_t = WPM_UPDATE_INTERVAL_SECS, value_parser = validate_sample_interval)]
+    sample_interval: f64,
+
+    /// Cap each WPM sample at this speed; raise it if your short bursts hit the cap (see also --uncapped-graph)
+    #[arg(long, value_name = "WPM", default_value_t = MAX_WPM_CAP, value_parser = validate_max_wpm)]
+    max_wpm: f64,
+
+    /// Seconds into the test before the first WPM sample, so the opening keys don't read as huge speeds
+    #[arg(long, value_name = "SECONDS", default_value_t = INITIAL_WPM_DELAY_SECS, value_parser = validate_wpm_delay)]
+    wpm_delay: f64,
+
+    /// Settling period in seconds before stats start being recorded
+    #[arg(long, default_value_t = 0)]
+    warmup: u64,
+
+    /// Start the clock only after this many correct characters in a row (default 5); they're excluded from stats
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "5", conflicts_with = "warmup", value_parser = clap::value_parser!(u64).range(1..))]
+    launch_gate: Option<u64>,
+
+    /// Allow skipping the rest of the current word with Ctrl+Enter
+    #[arg(long, default_value_t = false)]
+    allow_skip: bool,
+
+    /// Format of the saved test history: csv or jsonl
+    #[arg(long, default_value = "csv")]
+    history_format: HistoryFormat,
+
+    /// Keep history, key timings, settings and progress separate under this name
+    #[arg(long, value_parser = validate_profile_name)]
+    profile: Option<String>,
+
+    /// Store the typed passage in each history record (JSON Lines history only)
+    #[arg(long, default_value_t = false)]
+    save_text: bool,
+
+    /// Seed for text generation, to reproduce a previous passage
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Reuse the settings of the history record with the given --seed
+    #[arg(long, default_value_t = false, requires = "seed")]
+    replay_settings: bool,
+
+    /// Type today's passage, the same for everyone, and track your daily streak
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = [
+            "seed", "text_source", "file", "max_word_length", "case", "capitalize", "drill_bigrams",
+            "review", "cover_all_keys", "home_row", "include_numbers",
+            "include_punctuation", "playlist", "zen", "frequency_weighted", "only_keys",
+            "avoid_keys",
+        ]
+    )]
+    daily: bool,
+
+    /// Print a one-line summary of the last completed test on exit
+    #[arg(long, default_value_t = false)]
+    print_result: bool,
+
+    /// Plot and report true WPM samples instead of capping them
+    #[arg(long, default_value_t = false)]
+    uncapped_graph: bool,
+
+    /// Number of text columns while typing: 1, 2, or auto (2 on wide terminals)
+    #[arg(long, default_value = "1")]
+    columns: ColumnLayout,
+
+    /// Brighten the current and next word and dim the rest of the passage
+    #[arg(long, default_value_t = false)]
+    focus_word: bool,
+
+    /// Keep the line being typed on a fixed row and scroll the text up past it
+    #[arg(long, default_value_t = false)]
+    typewriter_scroll: bool,
+
+    /// Show live WPM as gross/net, where net discounts characters left wrong
+    #[arg(long, default_value_t = false)]
+    net_wpm: bool,
+
+    /// Tint the live stats line from green to red by current accuracy
+    #[arg(long, default_value_t = false)]
+    accuracy_bg: bool,
+
+    /// Hide the countdown while typing; the test still ends after the duration
+    #[arg(long, default_value_t = false)]
+    hide_timer: bool,
+
+    /// Condensed results screen for small terminals (used automatically when the full one won't fit)
+    #[arg(long, default_value_t = false)]
+    compact: bool,
+
+    /// Leave the keyboard heatmaps off the results screen
+    #[arg(long, default_value_t = false)]
+    no_heatmap: bool,
+
+    /// Leave the WPM graph off the results screen
+    #[arg(long, default_value_t = false)]
+    no_graph: bool,
+
+    /// Show the WPM graph building up below the passage while you type
+    #[arg(long, default_value_t = false, conflicts_with = "learn")]
+    live_graph: bool,
+
+    /// Record input-to-screen latency and keystroke intervals (press L on the results screen)
+    #[arg(long, default_value_t = false)]
+    debug: bool,
+
+    /// Terminal background the colors should suit: auto (from $COLFGBG, else dark), dark, or light
+    #[arg(long, default_value = "auto")]
+    background: Background,
+
+    /// Keyboard layout you type with: qwerty, dvorak, or colemak
+    #[arg(long, default_value = "qwerty")]
+    layout: KeyboardLayout,
+
+    /// Color heatmaps by physical key position (labelled with QWERTY keycaps)
+    #[arg(long, default_value_t = false)]
+    physical_heatmap: bool,
+
+    /// Write the speed and accuracy heatmaps to this SVG file after each completed test
+    #[arg(long, value_name = "PATH")]
+    export_heatmap: Option<PathBuf>,
+
+    /// Write every keystroke of each completed test to this CSV file
+    #[arg(long, value_name = "PATH")]
+    keylog: Option<PathBuf>,
+
+    /// Replay a --keylog file as fast as possible and print the stats, without the UI; pass the recorded test's --seed and options
+    #[arg(long, value_name = "KEYLOG", conflicts_with_all = ["warmup", "launch_gate", "menu", "resume", "compare"])]
+    bench: Option<PathBuf>,
+
+    /// Write a Markdown report of each completed test to this file
+    #[arg(long, value_name = "PATH")]
+    export_md: Option<PathBuf>,
+
+    /// Include the typed part of the passage in the --export-md report
+    #[arg(long, default_value_t = false, requires = "export_md")]
+    export_md_passage: bool,
+
+    /// Write a plain-text summary of each completed test, with a WPM sparkline, to this file
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+
+    /// Speed heatmap cutoffs (relative, fastest to slowest)
+    #[arg(long, default_value = "0.16,0.33,0.67,0.83", value_parser = validate_speed_thresholds)]
+    speed_thresholds: [f64; 4],
+
+    /// Accuracy heatmap cutoffs (fractions, best to worst)
+    #[arg(long, default_value = "0.95,0.85,0.70,0.50", value_parser = validate_accuracy_thresholds)]
+    accuracy_thresholds: [f64; 4],
+
+    /// Attempts a key needs before the accuracy heatmap colors it; sparser keys stay gray
+    #[arg(long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    min_key_samples: u64,
+
+    /// WPM goal used for the progress forecast on the summary screen
+    #[arg(long, default_value_t = 100.0)]
+    target_wpm: f64,
+
+    /// Move a pace marker through the text at this WPM to stay ahead of
+    #[arg(long, value_name = "WPM", value_parser = clap::value_parser!(u32).range(1..=MAX_WPM_CAP as i64))]
+    pacer: Option<u32>,
+
+    /// Print the results stored in a share code (press S on the summary screen to get one)
+    #[arg(long, value_name = "CODE")]
+    decode_share: Option<String>,
+
+    /// Run this many tests back to back, then show combined results
+    #[arg(long, value_name = "N", value_parser = validate_playlist_length)]
+    playlist: Option<usize>,
+
+    /// Start the next test automatically this many seconds after the results appear
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    auto_restart: Option<u64>,
+
+    /// Compare two runs from history by id (their timestamp)
+    #[arg(long, num_args = 2, value_names = ["ID1", "ID2"])]
+    compare: Option<Vec<u64>>,
+
+    /// Browse past results in a scrollable table instead of taking a test
+    #[arg(long, default_value_t = false, conflicts_with_all = ["compare", "menu", "bench"])]
+    show_history: bool,
+
+    /// Pick settings from a menu before the test (also shown when run without arguments)
+    #[arg(long, default_value_t = false)]
+    menu: bool,
+
+    /// Save the test's progress when quitting it with ESC or Ctrl+C, for --resume
+    #[arg(long, default_value_t = false)]
+    save_progress: bool,
+
+    /// Resume the test that was interrupted with ESC last time
+    #[arg(long, default_value_t = false, conflicts_with = "menu")]
+    resume: bool,
+
+    /// Fetch the passage from a URL (plain text works best)
+    #[cfg(feature = "url")]
+    #[arg(long, conflicts_with = "daily")]
+    url: Option<String>,
+
+    /// Serve live WPM and accuracy as JSON over HTTP on this address (e.g. 127.0.0.1:7878)
+    #[cfg(feature = "broadcast")]
+    #[arg(long, value_name = "ADDR")]
+    broadcast: Option<String>,
+}
+
+impl Args {
+    fn key_accuracy_requirement(&self) -> Result<Option<(char, f64)>, String> {
+        let Some(values) = &self.require_key_accuracy else {
+            return Ok(None);
+        };
+        let mut chars = values[0].chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(key), None) => key,
+            _ => return Err(format!("Expected a single key, got '{}'", values[0])),
+        };
+        let percent: f64 = values[1]
+            .trim_end_matches('%')
+            .parse()
+            .map_err(|_| format!("Expected a percentage, got '{}'", values[1]))?;
+        if !(0.0..=100.0).contains(&percent) {
+            return Err("Required key accuracy must be between 0 and 100".to_string());
+        }
+        Ok(Some((key, percent)))
+    }
+
+    fn apply_history_settings(&mut self, record: &TestHistory) -> Result<(), String> {
+        self.duration = record.duration_seconds;
+        self.require_correction = record.correction_mode;
+        self.text_source = record.text_source.parse()?;
+        self.max_word_length = record.max_word_length;
+        self.chars_per_word = record.chars_per_word;
+        if let Some(mode) = &record.mode {
+            self.mode = mode.parse()?;
+        }
+        if let Some(case) = &record.case {
+            self.case = case.parse()?;
+        }
+        Ok(())
+    }
+
+    fn apply_menu_settings(&mut self, settings: &MenuSettings) -> Result<(), String> {
+        self.duration = settings.duration_seconds;
+        self.text_source = settings.text_source.parse()?;
+        self.layout = settings.layout.parse()?;
+        self.mode = settings.mode.parse()?;
+        self.require_correction = settings.correction_mode;
+        Ok(())
+    }
+
+    fn apply_progress_settings(&mut self, progress: &SavedProgress) -> Result<(), String> {
+        self.duration = progress.duration_seconds;
+        self.require_correction = progress.correction_mode;
+        self.text_source = progress.text_source.parse()?;
+        self.max_word_length = progress.max_word_length;
+        self.seed = Some(progress.seed);
+        self.mode = progress.mode.parse()?;
+        Ok(())
+    }
+}
+
+fn validate_chars_per_word(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| "Must be a number")?;
+    if !(1.0..=20.0).contains(&value) {
+        Err("Characters per word must be between 1 and 20".to_string())
+    } else {
+        Ok(value)
+    }
+}
+
+fn validate_sample_interval(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| "Must be a number")?;
+    if !(MIN_SAMPLE_INTERVAL_SECS..=MAX_SAMPLE_INTERVAL_SECS).contains(&value) {
+        Err(format!(
+            "Sample interval must be between {} and {} seconds",
+            MIN_SAMPLE_INTERVAL_SECS, MAX_SAMPLE_INTERVAL_SECS
+        ))
+    } else {
+        Ok(value)
+    }
+}
+
+fn validate_max_wpm(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| "Must be a number")?;
+    if !(1.0..=MAX_WPM_CAP_LIMIT).contains(&value) {
+        Err(format!(
+            "Max WPM must be between 1 and {}",
+            MAX_WPM_CAP_LIMIT
+        ))
+    } else {
+        Ok(value)
+    }
+}
+
+fn validate_wpm_delay(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| "Must be a number")?;
+    if !(0.0..=MAX_WPM_DELAY_SECS).contains(&value) {
+        Err(format!(
+            "WPM delay must be between 0 and {} seconds",
+            MAX_WPM_DELAY_SECS
+        ))
+    } else {
+        Ok(value)
+    }
+}
+
+fn validate_playlist_length(s: &str) -> Result<usize, String> {
+    let value: usize = s.parse().map_err(|_| "Must be a positive integer")?;
+    if value == 0 {
+        Err("A playlist needs at least one test".to_string())
+    } else {
+        Ok(value)
+    }
+}
+
+fn validate_profile_name(s: &str) -> Result<String, String> {
+    // The name becomes part of a filename in the home directory
+    if s.is_empty() {
+        Err("Profile name can't be empty".to_string())
+    } else if !s
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Err("Profile names may only contain letters, digits, '-' and '_'".to_string())
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+fn validate_word_length(s: &str) -> Result<usize, String> {
+    let value: usize = s.parse().map_err(|_| "Must be a positive integer")?;
+    if value < MIN_WORD_LENGTH {
+        Err(format!("Word length must be at least {}", MIN_WORD_LENGTH))
+    } else if value > 20 {
+        Err("Word length must be 20 or less".to_string())
+    } else {
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TestHistory {
+    timestamp: u64,
+    duration_seconds: u64,
+    avg_wpm: f64,
+    peak_wpm: f64,
+    accuracy: f64,
+    characters_typed: usize,
+    errors: usize,
+    correction_mode: bool,
+    text_source: String,
+    max_word_length: usize,
+    // Missing in records written before seeds were recorded
+    #[serde(default)]
+    seed: Option<u64>,
+    // Uncapped peak, only recorded with --uncapped-graph
+    #[serde(default)]
+    true_peak_wpm: Option<f64>,
+    #[serde(default)]
+    chars_per_word: Option<f64>,
+    // Passage reached during the run, only with --save-text (JSON Lines history only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_text: Option<String>,
+    #[serde(default)]
+    mode: Option<String>,
+    // WPM graph and slowest keys (ms) for --compare (JSON Lines history only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wpm_data_points: Option<Vec<(f64, f64)>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slowest_keys: Option<Vec<(char, u64)>>,
+    // Set only on the combined record written at the end of a --playlist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    playlist_runs: Option<usize>,
+    // Only recorded with --word-accuracy
+    #[serde(default)]
+    word_accuracy: Option<f64>,
+    #[serde(default)]
+    burst_wpm: Option<f64>,
+    // Delay before the first keystroke; missing for resumed tests
+    #[serde(default)]
+    reaction_ms: Option<u64>,
+    #[serde(default)]
+    case: Option<String>,
+    #[serde(default)]
+    best_streak: Option<usize>,
+    // Restriction on the letters in the passage, e.g. "home-row"
+    #[serde(default)]
+    drill: Option<String>,
+    // Outcome of --require-key-accuracy; missing without one or if the key never came up
+    #[serde(default)]
+    key_accuracy_passed: Option<bool>,
+}
+
+impl TestHistory {
+    // Returns where an unrecognized old history was moved, so the caller can say so
+    fn append_csv(&self, path: &PathBuf) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        // Check if file exists to determine if we need to write header
+        let state = if path.exists() {
+            prepare_csv_history(path)?
+        } else {
+            CsvHistoryState::Empty
+        };
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        // Write CSV header if file is new
+        if !matches!(state, CsvHistoryState::Appendable) {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+
+        // Write the record
+        writeln!(
+            file,
+            "{},{},{:.2},{:.2},{:.2},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.timestamp,
+            self.duration_seconds,
+            self.avg_wpm,
+            self.peak_wpm,
+            self.accuracy,
+            self.characters_typed,
+            self.errors,
+            self.correction_mode,
+            self.text_source,
+            self.max_word_length,
+            self.seed.map_or(String::new(), |seed| seed.to_string()),
+            self.true_peak_wpm
+                .map_or(String::new(), |peak| format!("{:.2}", peak)),
+            self.chars_per_word
+                .map_or(String::new(), |chars| chars.to_string()),
+            self.mode.as_deref().unwrap_or_default(),
+            self.playlist_runs
+                .map_or(String::new(), |runs| runs.to_string()),
+            self.word_accuracy
+                .map_or(String::new(), |accuracy| format!("{:.2}", accuracy)),
+            self.burst_wpm
+                .map_or(String::new(), |burst| format!("{:.2}", burst)),
+            self.reaction_ms
+                .map_or(String::new(), |reaction| reaction.to_string()),
+            self.case.as_deref().unwrap_or_default(),
+            self.best_streak
+                .map_or(String::new(), |streak| streak.to_string()),
+            self.drill.as_deref().unwrap_or_default(),
+            self.key_accuracy_passed
+                .map_or(String::new(), |passed| passed.to_string())
+        )?;
+
+        Ok(match state {
+            CsvHistoryState::MovedAside(backup) => Some(backup),
+            _ => None,
+        })
+    }
+
+    fn from_csv_line(line: &str) -> Option<Self> {
+        // Columns are only ever appended, so parse by position and treat
+        // trailing columns missing from older rows as absent
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        if fields.len() < 10 {
+            return None;
+        }
+        let optional = |idx: usize| fields.get(idx).filter(|f| !f.is_empty());
+
+        Some(TestHistory {
+            timestamp: fields[0].parse().ok()?,
+            duration_seconds: fields[1].parse().ok()?,
+            avg_wpm: fields[2].parse().ok()?,
+            peak_wpm: fields[3].parse().ok()?,
+            accuracy: fields[4].parse().ok()?,
+            characters_typed: fields[5].parse().ok()?,
+            errors: fields[6].parse().ok()?,
+            correction_mode: fields[7].parse().ok()?,
+            text_source: fields[8].to_string(),
+            max_word_length: fields[9].parse().ok()?,
+            seed: optional(10).and_then(|f| f.parse().ok()),
+            true_peak_wpm: optional(11).and_then(|f| f.parse().ok()),
+            chars_per_word: optional(12).and_then(|f| f.parse().ok()),
+            target_text: None,
+            mode: optional(13).map(|f| f.to_string()),
+            wpm_data_points: None,
+            slowest_keys: None,
+            playlist_runs: optional(14).and_then(|f| f.parse().ok()),
+            word_accuracy: optional(15).and_then(|f| f.parse().ok()),
+            burst_wpm: optional(16).and_then(|f| f.parse().ok()),
+            reaction_ms: optional(17).and_then(|f| f.parse().ok()),
+            case: optional(18).map(|f| f.to_string()),
+            best_streak: optional(19).and_then(|f| f.parse().ok()),
+            drill: optional(20).map(|f| f.to_string()),
+            key_accuracy_passed: optional(21).and_then(|f| f.parse().ok()),
+        })
+    }
+
+    fn append_jsonl(&self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        // One self-describing record per line, so new fields don't break old readers
+        writeln!(file, "{}", serde_json::to_string(self)?)?;
+
+        Ok(())
+    }
+}
+
+// Snapshot of an interrupted test. Per-key timings and the WPM graph are not
+// kept, so stats after resuming only approximate an uninterrupted run.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedProgress {
+    target_text: String,
+    user_input: String,
+    current_position: usize,
+    elapsed_ms: u64,
+    errors: usize,
+    total_keystrokes: usize,
+    correction_attempts: Vec<bool>,
+    duration_seconds: u64,
+    correction_mode: bool,
+    text_source: String,
+    max_word_length: usize,
+    seed: u64,
+    mode: String,
+}
+
+// Choices made in the settings menu, reused as its defaults next time
+#[derive(Debug, Serialize, Deserialize)]
+struct MenuSettings {
+    duration_seconds: u64,
+    text_source: String,
+    layout: String,
+    mode: String,
+    correction_mode: bool,
+}
+
+struct MenuField {
+    label: &'static str,
+    options: Vec<String>,
+    choice: usize,
+}
+
+impl MenuField {
+    fn new(label: &'static str, options: &[&str], current: String) -> Self {
+        // Values given on the command line that aren't a preset (e.g. a file path) become an extra option
+        let mut options: Vec<String> = options.iter().map(|option| option.to_string()).collect();
+        let choice = match options.iter().position(|option| *option == current) {
+            Some(choice) => choice,
+            None => {
+                options.push(current);
+                options.len() - 1
+            }
+        };
+        Self {
+            label,
+            options,
+            choice,
+        }
+    }
+
+    fn value(&self) -> &str {
+        &self.options[self.choice]
+    }
+}
+
+struct SettingsMenu {
+    fields: Vec<MenuField>,
+    selected: usize,
+    background: Background,
+}
+
+impl SettingsMenu {
+    fn new(args: &Args) -> Self {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" }.to_string();
+        Self {
+            fields: vec![
+                MenuField::new("Duration (s)", &MENU_DURATIONS, args.duration.to_string()),
+                MenuField::new(
+                    "Text Source",
+                    &["google", "system", "builtin", "auto"],
+                    args.text_source.to_string(),
+                ),
+                MenuField::new(
+                    "Layout",
+                    &["qwerty", "dvorak", "colemak"],
+                    args.layout.to_string(),
+                ),
+                MenuField::new(
+                    "Mode",
+                    &["timed", "accuracy", "words", "zen"],
+                    args.mode.to_string(),
+                ),
+                MenuField::new(
+                    "Require Correction",
+                    &["off", "on"],
+                    on_off(args.require_correction),
+                ),
+            ],
+            selected: 0,
+            background: args.background.resolve(),
+        }
+    }
+
+    fn handle_key_event(&mut self, key: KeyCode) {
+        let field_count = self.fields.len();
+        let field = &mut self.fields[self.selected];
+        let option_count = field.options.len();
+        match key {
+            KeyCode::Up => self.selected = (self.selected + field_count - 1) % field_count,
+            KeyCode::Down | KeyCode::Tab => self.selected = (self.selected + 1) % field_count,
+            KeyCode::Left => field.choice = (field.choice + option_count - 1) % option_count,
+            KeyCode::Right | KeyCode::Char(' ') => field.choice = (field.choice + 1) % option_count,
+            _ => {}
+        }
+    }
+
+    fn get_settings(&self) -> Result<MenuSettings, String> {
+        Ok(MenuSettings {
+            duration_seconds: self.fields[0]
+                .value()
+                .parse()
+                .map_err(|_| "Invalid duration")?,
+            text_source: self.fields[1].value().to_string(),
+            layout: self.fields[2].value().to_string(),
+            mode: self.fields[3].value().to_string(),
+            correction_mode: self.fields[4].value() == "on",
+        })
+    }
+}
+
+// Result summary others can decode with --decode-share. Short field names keep
+// the encoded code small enough to paste into a chat message.
+#[derive(Debug, Serialize, Deserialize)]
+struct ShareCode {
+    #[serde(rename = "w")]
+    avg_wpm: f64,
+    #[serde(rename = "p")]
+    peak_wpm: f64,
+    #[serde(rename = "a")]
+    accuracy: f64,
+    #[serde(rename = "d")]
+    duration_seconds: u64,
+    #[serde(rename = "s")]
+    text_source: String,
+    #[serde(rename = "k")]
+    slowest_keys: String,
+    #[serde(rename = "e")]
+    problem_keys: String,
+}
+
+impl ShareCode {
+    fn encode(&self) -> Result<String, serde_json::Error> {
+        let json = serde_json::to_string(self)?;
+        Ok(format!(
+            "{}{}",
+            SHARE_CODE_PREFIX,
+            URL_SAFE_NO_PAD.encode(json)
+        ))
+    }
+
+    fn decode(code: &str) -> Result<Self, Box<dyn Error>> {
+        let encoded = code
+            .trim()
+            .strip_prefix(SHARE_CODE_PREFIX)
+            .ok_or("Not a ratatype share code")?;
+        let json = URL_SAFE_NO_PAD.decode(encoded)?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+impl std::fmt::Display for ShareCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Average WPM:  {:.1}", self.avg_wpm)?;
+        writeln!(f, "Peak WPM:     {:.1}", self.peak_wpm)?;
+        writeln!(f, "Accuracy:     {:.1}%", self.accuracy)?;
+        writeln!(f, "Duration:     {}s", self.duration_seconds)?;
+        writeln!(f, "Text Source:  {}", self.text_source)?;
+        writeln!(f, "Slowest Keys: {}", self.slowest_keys)?;
+        write!(f, "Problem Keys: {}", self.problem_keys)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HeatmapConfig {
+    // Relative speed cutoffs, 0.0 = fastest key and 1.0 = slowest key
+    speed_thresholds: [f64; 4],
+    // Accuracy cutoffs as fractions, from best to worst
+    accuracy_thresholds: [f64; 4],
+    // Attempts needed before a key's accuracy is trusted enough to color
+    min_key_samples: usize,
+}
+
+fn parse_thresholds(s: &str) -> Result<[f64; 4], String> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_| "Thresholds must be numbers between 0 and 1")?;
+    let thresholds: [f64; 4] = values
+        .try_into()
+        .map_err(|_| "Exactly four comma-separated thresholds are required")?;
+    if thresholds.iter().any(|t| !(0.0..=1.0).contains(t)) {
+        return Err("Thresholds must be between 0 and 1".to_string());
+    }
+    Ok(thresholds)
+}
+
+fn validate_speed_thresholds(s: &str) -> Result<[f64; 4], String> {
+    let thresholds = parse_thresholds(s)?;
+    if thresholds.windows(2).any(|w| w[0] > w[1]) {
+        return Err("Speed thresholds must be in ascending order".to_string());
+    }
+    Ok(thresholds)
+}
+
+fn validate_accuracy_thresholds(s: &str) -> Result<[f64; 4], String> {
+    let thresholds = parse_thresholds(s)?;
+    if thresholds.windows(2).any(|w| w[0] < w[1]) {
+        return Err("Accuracy thresholds must be in descending order".to_string());
+    }
+    Ok(thresholds)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KeyboardLayout {
+    Qwerty,
+    Dvorak,
+    Colemak,
+}
+
+impl KeyboardLayout {
+    // Characters produced by the three letter rows of physical keys, left to right
+    // (the ten keys from Q to P, A to ;, and Z to / on a QWERTY keyboard)
+    fn rows(&self) -> [&'static str; 3] {
+        match self {
+            KeyboardLayout::Qwerty => ["qwertyuiop", "asdfghjkl;", "zxcvbnm,./"],
+            KeyboardLayout::Dvorak => ["',.pyfgcrl", "aoeuidhtns", ";qjkxbmwvz"],
+            KeyboardLayout::Colemak => ["qwfpgjluy;", "arstdhneio", "zxcvbkm,./"],
+        }
+    }
+
+    // (row, column) of the physical key that produces this character
+    fn key_position(&self, key: char) -> Option<(usize, usize)> {
+        let key = key.to_ascii_lowercase();
+        self.rows().iter().enumerate().find_map(|(row, keys)| {
+            keys.chars()
+                .position(|c| c == key)
+                .map(|column| (row, column))
+        })
+    }
+
+    fn are_adjacent(&self, a: char, b: char) -> bool {
+        match (self.key_position(a), self.key_position(b)) {
+            (Some((row_a, column_a)), Some((row_b, column_b))) => {
+                (row_a, column_a) != (row_b, column_b)
+                    && row_a.abs_diff(row_b) <= 1
+                    && column_a.abs_diff(column_b) <= 1
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for KeyboardLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "qwerty" => Ok(KeyboardLayout::Qwerty),
+            "dvorak" => Ok(KeyboardLayout::Dvorak),
+            "colemak" => Ok(KeyboardLayout::Colemak),
+            _ => Err(format!(
+                "Invalid layout '{}'. Valid options: qwerty, dvorak, colemak",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for KeyboardLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyboardLayout::Qwerty => write!(f, "qwerty"),
+            KeyboardLayout::Dvorak => write!(f, "dvorak"),
+            KeyboardLayout::Colemak => write!(f, "colemak"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Hand {
+    Left,
+    Right,
+}
+
+impl std::fmt::Display for Hand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Hand::Left => write!(f, "left"),
+            Hand::Right => write!(f, "right"),
+        }
+    }
+}
+
+impl Hand {
+    fn for_key(key: char, layout: KeyboardLayout) -> Option<Hand> {
+        layout.key_position(key).map(|(_, column)| {
+            if column < LEFT_HAND_KEYS_PER_ROW {
+                Hand::Left
+            } else {
+                Hand::Right
+            }
+        })
+    }
+}
+
+// Touch-typing finger for each key column, mirrored between the hands
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Finger {
+    Pinky,
+    Ring,
+    Middle,
+    Index,
+}
+
+impl Finger {
+    const ALL: [Finger; 4] = [Finger::Pinky, Finger::Ring, Finger::Middle, Finger::Index];
+
+    fn for_key(key: char, layout: KeyboardLayout) -> Option<(Hand, Finger)> {
+        let (_, column) = layout.key_position(key)?;
+        let hand = Hand::for_key(key, layout)?;
+        // Index fingers cover the two middle columns on each side
+        let finger = match column {
+            0 | 9 => Finger::Pinky,
+            1 | 8 => Finger::Ring,
+            2 | 7 => Finger::Middle,
+            _ => Finger::Index,
+        };
+        Some((hand, finger))
+    }
+}
+
+impl std::fmt::Display for Finger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Finger::Pinky => write!(f, "pinky"),
+            Finger::Ring => write!(f, "ring"),
+            Finger::Middle => write!(f, "middle"),
+            Finger::Index => write!(f, "index"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ErrorKind {
+    // Typed the next character early, or the previous one late after doing so
+    Transposition,
+    // Hit a key next to the right one
+    AdjacentKey,
+    // Pressed the previous key again
+    Insertion,
+    // Right letter, wrong case
+    Case,
+    Other,
+}
+
+// Views of the results screen, switched with Left/Right or the number keys
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SummaryTab {
+    Overview,
+    Speed,
+    Accuracy,
+    Bigrams,
+    Errors,
+    Graph,
+}
+
+impl SummaryTab {
+    const ALL: [SummaryTab; 6] = [
+        SummaryTab::Overview,
+        SummaryTab::Speed,
+        SummaryTab::Accuracy,
+        SummaryTab::Bigrams,
+        SummaryTab::Errors,
+        SummaryTab::Graph,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            SummaryTab::Overview => "Overview",
+            SummaryTab::Speed => "Speed Heatmap",
+            SummaryTab::Accuracy => "Accuracy Heatmap",
+            SummaryTab::Bigrams => "Bigrams",
+            SummaryTab::Errors => "Errors",
+            SummaryTab::Graph => "Graph",
+        }
+    }
+
+    fn next(&self) -> SummaryTab {
+        Self::ALL[(*self as usize + 1) % Self::ALL.len()]
+    }
+
+    fn previous(&self) -> SummaryTab {
+        Self::ALL[(*self as usize + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::Transposition => write!(f, "transpositions"),
+            ErrorKind::AdjacentKey => write!(f, "adjacent keys"),
+            ErrorKind::Insertion => write!(f, "insertions"),
+            ErrorKind::Case => write!(f, "case"),
+            ErrorKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct HandStats {
+    total_time: Duration,
+    attempts: usize,
+    errors: usize,
+}
+
+impl HandStats {
+    fn average_time(&self) -> Option<Duration> {
+        if self.attempts == 0 {
+            None
+        } else {
+            Some(self.total_time / self.attempts as u32)
+        }
+    }
+
+    fn accuracy(&self) -> Option<f64> {
+        if self.attempts == 0 {
+            None
+        } else {
+            Some((self.attempts - self.errors) as f64 / self.attempts as f64 * 100.0)
+        }
+    }
+}
+
+// Results of one test in a --playlist session
+#[derive(Debug, Clone, Copy)]
+struct PlaylistRun {
+    duration: Duration,
+    avg_wpm: f64,
+    peak_wpm: f64,
+    accuracy: f64,
+    characters_typed: usize,
+    errors: usize,
+    total_keystrokes: usize,
+}
+
+// Time from one letter to the next, accumulated across sessions
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct BigramStats {
+    total_ms: u64,
+    count: u64,
+}
+
+impl BigramStats {
+    fn average_ms(&self) -> f64 {
+        self.total_ms as f64 / self.count.max(1) as f64
+    }
+}
+
+// Spaced repetition state for a word you've missed: every clean review
+// doubles the wait before it comes up again, every miss starts it over
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct WordReview {
+    misses: u32,
+    interval_days: u64,
+    // Unix timestamps in seconds
+    due: u64,
+    last_seen: u64,
+}
+
+// Best result of each day's --daily passage, keyed by days since the Unix epoch
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct DailyResult {
+    best_wpm: f64,
+    accuracy: f64,
+    attempts: u32,
+}
+
+// One row of the --keylog CSV
+#[derive(Debug, Clone, Copy)]
+struct Keystroke {
+    elapsed_ms: u64,
+    target: char,
+    typed: char,
+    response_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct KeyMetrics {
+    times: Vec<Duration>,
+    errors: usize,
+}
+
+impl KeyMetrics {
+    fn new() -> Self {
+        Self {
+            times: Vec::new(),
+            errors: 0,
+        }
+    }
+
+    fn average_time(&self) -> Option<Duration> {
+        if self.times.is_empty() {
+            None
+        } else {
+            let total_nanos: u64 = self.times.iter().map(|d| d.as_nanos() as u64).sum();
+            Some(Duration::from_nanos(total_nanos / self.times.len() as u64))
+        }
+    }
+}
+
+struct App {
+    target_text: String,
+    user_input: String,
+    current_position: usize,
+    start_time: Option<Instant>,
+    wpm_history: Vec<f64>,
+    wpm_data_points: Vec<(f64, f64)>, // (time, wpm) for graphing
+    // Uncapped samples, used for graph/stats with --uncapped-graph
+    raw_wpm_history: Vec<f64>,
+    raw_wpm_data_points: Vec<(f64, f64)>,
+    // Net WPM at each sample: uncorrected errors cost a word each
+    net_wpm_history: Vec<f64>,
+    uncapped_graph: bool,
+    columns: ColumnLayout,
+    background: Background,
+    typewriter_scroll: bool,
+    focus_word: bool,
+    hide_timer: bool,
+    accuracy_bg: bool,
+    learn: bool,
+    hint_delay: Duration,
+    net_wpm: bool,
+    compact: bool,
+    show_heatmaps: bool,
+    show_graph: bool,
+    live_graph: bool,
+    test_duration: Duration,
+    word_count: usize,
+    is_finished: bool,
+    errors: usize,
+    total_keystrokes: usize,
+    last_wpm_update: Option<Instant>,
+    require_correction: bool,
+    error_behavior: ErrorBehavior,
+    strict_space: bool,
+    space_errors: usize,
+    debounce: bool,
+    last_char_event: Option<(char, Instant)>,
+    bell_on_error: bool,
+    // Set on a mistake and cleared once run_app has rung the bell
+    bell_pending: bool,
+    last_bell: Option<Instant>,
+    // Consecutive correct keystrokes, reset by any mistake
+    current_streak: usize,
+    best_streak: usize,
+    allow_skip: bool,
+    correction_attempts: Vec<bool>, // Track which positions had errors
+    retry_counts: Vec<usize>,       // Rejected attempts per position (correction mode)
+    text_source: TextSource,
+    max_word_length: usize,
+    case: PassageCase,
+    capitalize: Option<f64>,
+    cover_all_keys: bool,
+    include_numbers: bool,
+    include_punctuation: bool,
+    injection_rate: f64,
+    word_accuracy: bool,
+    required_key_accuracy: Option<(char, f64)>,
+    chars_per_word: f64,
+    history_format: HistoryFormat,
+    profile: Option<String>,
+    save_text: bool,
+    sample_texts: Vec<String>,
+    // Seeded per run so any passage can be regenerated from its seed
+    seed: u64,
+    rng: StdRng,
+    // Cache for performance
+    target_chars: Vec<char>,
+    // Key analytics tracking
+    key_metrics: HashMap<char, KeyMetrics>,
+    bigram_metrics: HashMap<String, BigramStats>,
+    error_kinds: HashMap<ErrorKind, usize>,
+    // Wrong spellings of each word, kept across restarts: word -> typed -> count
+    word_typos: HashMap<String, HashMap<String, usize>>,
+    // Words up to here have already been checked for typos
+    typo_checked_position: usize,
+    // First wrong character typed where the cursor held still (correction mode or
+    // --error-behavior stay), since user_input only ever gets the right one there
+    rejected_chars: HashMap<usize, char>,
+    // Slowest letter pairs from past sessions, used with --drill-bigrams
+    slow_bigrams: Vec<String>,
+    frequency_weighted: bool,
+    // Word list filters from --only-keys and --avoid-keys, lowercased
+    only_keys: Option<String>,
+    avoid_keys: Option<String>,
+    // Set once the filters are dropped, so the stats line can say why they did nothing
+    key_filter_ignored: bool,
+    // Why a --file passage fell back to built-in text, for the stats line
+    passage_fallback: Option<&'static str>,
+    // Word lists kept between restarts, with the max_word_length they were filtered for
+    google_words: Option<(usize, Vec<String>)>,
+    dict_words: Option<(usize, Vec<String>)>,
+    // Words due for review, used with --review
+    review: bool,
+    review_words: Vec<String>,
+    home_row: bool,
+    heatmap: HeatmapConfig,
+    layout: KeyboardLayout,
+    physical_heatmap: bool,
+    export_heatmap: Option<PathBuf>,
+    keylog: Option<PathBuf>,
+    keystrokes: Vec<Keystroke>,
+    export_md: Option<PathBuf>,
+    export_md_passage: bool,
+    report: Option<PathBuf>,
+    last_keystroke_time: Option<Instant>,
+    current_key_start_time: Option<Instant>,
+    // Warmup tracking (keystrokes before stats begin)
+    warmup_duration: Duration,
+    // Correct characters in a row needed before stats begin, with --launch-gate
+    launch_gate: Option<usize>,
+    warmup_start: Option<Instant>,
+    stats_start_position: usize,
+    save_progress: bool,
+    progress_saved: bool,
+    // Unrecognized CSV history and where it was moved before starting a new one
+    history_backup: Option<(PathBuf, PathBuf)>,
+    // Summary of the last completed test for --print-result; kept across restarts
+    last_result_line: Option<String>,
+    // Problems met while finishing a test, printed once the terminal is restored
+    deferred_warnings: Vec<String>,
+    // Stats shared with the --broadcast server thread
+    #[cfg(feature = "broadcast")]
+    live_stats: Option<std::sync::Arc<std::sync::Mutex<LiveStats>>>,
+    typed_times: Vec<Duration>,
+    burst_window: Duration,
+    sample_interval: f64,
+    // --max-wpm and --wpm-delay
+    max_wpm: f64,
+    wpm_delay: f64,
+    finish_word: Option<Overtime>,
+    in_overtime: bool,
+    replay_start: Option<Instant>,
+    show_share_code: bool,
+    show_char_frequency: bool,
+    summary_tab: SummaryTab,
+    // Input lag diagnostics, only recorded with --debug
+    debug: bool,
+    input_received: Option<Instant>,
+    last_key_event: Option<Instant>,
+    frame_latencies: Vec<Duration>,
+    key_intervals: Vec<Duration>,
+    show_latency: bool,
+    playlist_length: Option<usize>,
+    auto_restart: Option<Duration>,
+    restart_deadline: Option<Instant>,
+    // When the passage was put on screen, to time the first keystroke against
+    ready_time: Option<Instant>,
+    reaction_time: Option<Duration>,
+    playlist_runs: Vec<PlaylistRun>,
+    show_playlist_summary: bool,
+    target_wpm: f64,
+    pacer: Option<u32>,
+    wpm_forecast: Option<String>,
+    wpm_rank: Option<String>,
+    // Previous best average WPM for this duration and source, when this run beat it
+    beaten_best: Option<f64>,
+    // Day of the --daily passage, and how today's attempt compares
+    daily: Option<u64>,
+    daily_summary: Option<String>,
+    finish_time: Option<Instant>,
+    // Set while paused with Ctrl+P; the clock is frozen at this instant
+    paused_at: Option<Instant>,
+    paused_total: Duration,
+    mode: TestMode,
+}
+
+impl App {
+    fn new(args: &Args) -> App {
+        let sample_texts = vec![
+            "The quick brown fox jumps over the lazy dog. This pangram contains every letter of the alphabet at least once.".to_string(),
+            "In a hole in the ground there lived a hobbit. Not a nasty, dirty, wet hole filled with the ends of worms and an oozy smell.".to_string(),
+            "To be or not to be, that is the question. Whether 'tis nobler in the mind to suffer the slings and arrows of outrageous fortune.".to_string(),
+            "It was the best of times, it was the worst of times, it was the age of wisdom, it was the age of foolishness and doubt.".to_string(),
+            "All human beings are born free and equal in dignity and rights. They are endowed with reason and conscience.".to_string(),
+            "The only way to do great work is to love what you do. If you haven't found it yet, keep looking and don't settle.".to_string(),
+            "Two things are infinite: the universe and human stupidity; and I'm not sure about the universe and its vast mysteries.".to_string(),
+            "In the midst of winter, I found there was, within me, an invincible summer that could not be defeated by any force.".to_string(),
+        ];
+
+        let daily = args.daily.then(today);
+        let seed = match daily {
+            Some(day) => daily_seed(day),
+            None => args.seed.unwrap_or_else(|| rand::thread_rng().r#gen()),
+        };
+
+        let mut app = App {
+            target_text: String::new(),
+            user_input: String::new(),
+            current_position: 0,
+            start_time: None,
+            wpm_history: Vec::new(),
+            wpm_data_points: Vec::new(),
+            raw_wpm_history: Vec::new(),
+            net_wpm_history: Vec::new(),
+            raw_wpm_data_points: Vec::new(),
+            uncapped_graph: args.uncapped_graph,
+            columns: args.columns,
+            background: args.background.resolve(),
+            typewriter_scroll: args.typewriter_scroll,
+            focus_word: args.focus_word,
+            hide_timer: args.hide_timer,
+            accuracy_bg: args.accuracy_bg,
+            learn: args.learn,
+            hint_delay: Duration::from_secs(args.hint_delay),
+            net_wpm: args.net_wpm,
+            compact: args.compact,
+            show_heatmaps: !args.no_heatmap,
+            show_graph: !args.no_graph,
+            live_graph: args.live_graph,
+            test_duration: Duration::from_secs(args.duration),
+            word_count: args
+                .words
+                .map_or(DEFAULT_WORD_COUNT, |words| words as usize),
+            is_finished: false,
+            errors: 0,
+            total_keystrokes: 0,
+            last_wpm_update: None,
+            require_correction: args.require_correction,
+            error_behavior: args.error_behavior,
+            strict_space: args.strict_space,
+            space_errors: 0,
+            debounce: args.debounce,
+            bell_on_error: args.bell_on_error,
+            bell_pending: false,
+            last_bell: None,
+            last_char_event: None,
+            current_streak: 0,
+            best_streak: 0,
+            target_wpm: args.target_wpm,
+            pacer: args.pacer,
+            show_share_code: false,
+            show_char_frequency: false,
+            summary_tab: SummaryTab::Overview,
+            debug: args.debug,
+            input_received: None,
+            last_key_event: None,
+            frame_latencies: Vec::new(),
+            key_intervals: Vec::new(),
+            show_latency: false,
+            typed_times: Vec::new(),
+            burst_window: Duration::from_secs(args.burst_window),
+            sample_interval: args.sample_interval,
+            max_wpm: args.max_wpm,
+            wpm_delay: args.wpm_delay,
+            finish_word: args.finish_word,
+            in_overtime: false,
+            replay_start: None,
+            playlist_length: args.playlist,
+            auto_restart: args.auto_restart.map(Duration::from_secs),
+            restart_deadline: None,
+            ready_time: Some(Instant::now()),
+            reaction_time: None,
+            playlist_runs: Vec::new(),
+            show_playlist_summary: false,
+            wpm_forecast: None,
+            wpm_rank: None,
+            beaten_best: None,
+            daily,
+            daily_summary: None,
+            allow_skip: args.allow_skip,
+            cover_all_keys: args.cover_all_keys,
+            include_numbers: args.include_numbers,
+            include_punctuation: args.include_punctuation,
+            injection_rate: args.injection_rate as f64 / 100.0,
+            word_accuracy: args.word_accuracy,
+            // Checked in main before the terminal is set up
+            required_key_accuracy: args.key_accuracy_requirement().ok().flatten(),
+            correction_attempts: Vec::new(),
+            retry_counts: Vec::new(),
+            text_source: args.text_source.clone(),
+            max_word_length: args.max_word_length,
+            case: args.case,
+            capitalize: args.capitalize.map(|percent| percent as f64 / 100.0),
+            chars_per_word: args
+                .chars_per_word
+                .unwrap_or_else(|| args.text_source.default_chars_per_word()),
+            history_format: args.history_format,
+            profile: args.profile.clone(),
+            save_text: args.save_text,
+            sample_texts,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            target_chars: Vec::new(),
+            key_metrics: HashMap::new(),
+            bigram_metrics: HashMap::new(),
+            error_kinds: HashMap::new(),
+            word_typos: HashMap::new(),
+            typo_checked_position: 0,
+            rejected_chars: HashMap::new(),
+            slow_bigrams: if args.drill_bigrams {
+                get_slow_bigrams(args.profile.as_deref())
+            } else {
+                Vec::new()
+            },
+            review: args.review,
+            home_row: args.home_row,
+            review_words: if args.review {
+                get_due_words(args.profile.as_deref())
+            } else {
+                Vec::new()
+            },
+            frequency_weighted: args.frequency_weighted,
+            only_keys: args.only_keys.as_deref().map(str::to_lowercase),
+            avoid_keys: args.avoid_keys.as_deref().map(str::to_lowercase),
+            key_filter_ignored: false,
+            passage_fallback: None,
+            google_words: None,
+            dict_words: None,
+            heatmap: HeatmapConfig {
+                speed_thresholds: args.speed_thresholds,
+                accuracy_thresholds: args.accuracy_thresholds,
+                min_key_samples: args.min_key_samples as usize,
+            },
+            layout: args.layout,
+            physical_heatmap: args.physical_heatmap,
+            export_heatmap: args.export_heatmap.clone(),
+            keylog: args.keylog.clone(),
+            keystrokes: Vec::new(),
+            export_md: args.export_md.clone(),
+            export_md_passage: args.export_md_passage,
+            report: args.report.clone(),
+            last_keystroke_time: None,
+            current_key_start_time: None,
+            warmup_duration: Duration::from_secs(args.warmup),
+            launch_gate: args.launch_gate.map(|needed| needed as usize),
+            warmup_start: None,
+            stats_start_position: 0,
+            save_progress: args.save_progress,
+            progress_saved: false,
+            history_backup: None,
+            last_result_line: None,
+            deferred_warnings: Vec::new(),
+            #[cfg(feature = "broadcast")]
+            live_stats: None,
+            finish_time: None,
+            paused_at: None,
+            paused_total: Duration::ZERO,
+            // Lessons are untimed passages, scored like accuracy tests
+            mode: if args.learn {
+                TestMode::Accuracy
+            } else if args.words.is_some() {
+                TestMode::Words
+            } else if args.zen {
+                TestMode::Zen
+            } else {
+                args.mode
+            },
+        };
+
+        app.generate_text();
+        app.start_timing_current_key();
+        app
+    }
+
+    fn start_timing_current_key(&mut self) {
+        if self.current_position < self.target_chars.len() {
+            self.current_key_start_time = Some(Instant::now());
+        }
+    }
+    
+    fn is_warming_up(&self) -> bool {
+        self.start_time.is_none() && self.warmup_start.is_some()
+    }
+
+    fn get_warmup_remaining(&self) -> Duration {
+        self.warmup_start.map_or(self.warmup_duration, |start| {
+            self.warmup_duration.saturating_sub(start.elapsed())
+        })
+    }
+
+    fn begin_recording(&mut self) {
+        // Stats begin fresh from here: anything typed during warmup is discarded
+        let now = Instant::now();
+        self.start_time = Some(now);
+        self.last_keystroke_time = Some(now);
+        self.errors = 0;
+        self.space_errors = 0;
+        self.current_streak = 0;
+        self.best_streak = 0;
+        self.total_keystrokes = 0;
+        self.key_metrics.clear();
+        self.bigram_metrics.clear();
+        self.error_kinds.clear();
+        self.keystrokes.clear();
+        self.stats_start_position = self.current_position;
+        self.start_timing_current_key();
+    }
+
+    fn check_warmup(&mut self) {
+        // The launch gate opens on accuracy rather than after a fixed time
+        let settled = match self.launch_gate {
+            Some(needed) => self.current_streak >= needed,
+            None => self.get_warmup_remaining().is_zero(),
+        };
+        if self.is_warming_up() && settled {
+            self.begin_recording();
+        }
+    }
+
+    fn get_characters_typed(&self) -> usize {
+        self.current_position
+            .saturating_sub(self.stats_start_position)
+    }
+
+    fn get_column_count(&self, width: u16) -> usize {
+        // A single anchored line only makes sense with one column
+        if self.typewriter_scroll {
+            return 1;
+        }
+        match self.columns {
+            ColumnLayout::One => 1,
+            ColumnLayout::Two => 2,
+            ColumnLayout::Auto if width >= TWO_COLUMN_MIN_WIDTH => 2,
+            ColumnLayout::Auto => 1,
+        }
+    }
+
+    fn is_code_mode(&self) -> bool {
+        matches!(self.text_source, TextSource::File(_))
+    }
+    
+    fn skip_leading_whitespace(&mut self) {
+        if !self.is_code_mode() {
+            return;
+        }
+        
+        // Skip leading spaces and tabs at the current position
+        while self.current_position < self.target_chars.len() {
+            let ch = self.target_chars[self.current_position];
+            if ch == ' ' || ch == '\t' {
+                self.current_position += 1;
+            } else {
+                break;
+            }
+        }
+        
+        // Ensure user_input matches the skipped position
+        while self.user_input.len() < self.current_position {
+            let ch = self.target_chars[self.user_input.len()];
+            self.user_input.push(ch);
+        }
+    }
+
+    fn required_text_length(&self) -> usize {
+        // Accuracy tests are a short passage that has to be completed
+        if self.mode == TestMode::Accuracy {
+            return ACCURACY_PASSAGE_LENGTH;
+        }
+        // Word-count tests are cut to length once generated
+        if self.mode == TestMode::Words {
+            return (self.word_count * WORDS_MODE_CHARS_PER_WORD).max(MIN_TEXT_LENGTH);
+        }
+        // Zen mode tops the text up as it goes
+        if self.mode == TestMode::Zen {
+            return MIN_TEXT_LENGTH;
+        }
+
+        // Enough for the whole run, warmup included, at the default WPM cap: nobody
+        // sustains that for a whole test, so nobody runs out before time is up.
+        // --max-wpm only clamps samples; it doesn't change the passage length
+        let typing_secs = (self.test_duration + self.warmup_duration).as_secs_f64();
+        let chars_per_sec = MAX_WPM_CAP / 60.0 * self.chars_per_word;
+        let chars_needed = (chars_per_sec * typing_secs * TEXT_LENGTH_MARGIN) as usize;
+
+        // For code mode, be more generous to ensure we don't run out
+        let multiplier = if self.is_code_mode() { 2.0 } else { 1.0 };
+        let adjusted_chars = (chars_needed as f64 * multiplier) as usize;
+
+        // Ensure we have at least the minimum length
+        adjusted_chars.max(MIN_TEXT_LENGTH)
+    }
+
+    fn generate_text(&mut self) {
+        self.target_text = self.generate_passage();
+        // Cache character vector for performance and initialize correction_attempts
+        self.target_chars = self.target_text.chars().collect();
+        self.correction_attempts = vec![false; self.target_chars.len()];
+        self.retry_counts = vec![0; self.target_chars.len()];
+        
+        // Skip leading whitespace at the beginning for code mode
+        self.skip_leading_whitespace();
+    }
+
+    fn extend_zen_text(&mut self) {
+        // Zen text never runs out: more is appended well before the cursor gets there
+        let remaining = self.target_chars.len() - self.current_position;
+        if self.mode != TestMode::Zen || remaining > ZEN_REFILL_MARGIN {
+            return;
+        }
+        let more = self.generate_passage();
+        if !self.target_text.is_empty() && !self.target_text.ends_with(char::is_whitespace) {
+            let separator = if self.is_code_mode() { '\n' } else { ' ' };
+            self.target_text.push(separator);
+            self.target_chars.push(separator);
+        }
+        self.target_text.push_str(&more);
+        self.target_chars.extend(more.chars());
+        // Per-position state has to cover the new text too
+        let len = self.target_chars.len();
+        self.correction_attempts.resize(len, false);
+        self.retry_counts.resize(len, 0);
+    }
+
+    fn generate_passage(&mut self) -> String {
+        // History records the source actually used, so auto is settled before generating
+        if self.text_source == TextSource::Auto {
+            self.text_source = self.pick_auto_source();
+        }
+
+        // Review sessions use due words in place of the word list; code is never reviewed
+        let text = if !self.review_words.is_empty() && !self.is_code_mode() {
+            let words = self.review_words.clone();
+            self.generate_word_text(&words, false)
+        } else if self.home_row && !self.is_code_mode() {
+            let words = self.load_home_row_words();
+            self.generate_word_text(&words, false)
+        } else {
+            self.generate_source_text()
+        };
+        let mut text = if self.mode == TestMode::Words {
+            truncate_to_words(&text, self.word_count).to_string()
+        } else {
+            text
+        };
+        if let Some(rate) = self.capitalize
+            && !self.is_code_mode()
+        {
+            text = self.capitalize_words(&text, rate);
+        }
+        self.case.apply(&text)
+    }
+
+    fn generate_source_text(&mut self) -> String {
+        match self.text_source.clone() {
+            TextSource::Auto => unreachable!("auto is resolved before generating"),
+            TextSource::Google10k => self.generate_google10k_text(),
+            TextSource::SystemDict => self.generate_system_dict_text(),
+            TextSource::Builtin => self.generate_builtin_text(),
+            TextSource::File(path) => self.generate_file_text(&path),
+            TextSource::Passage(path) => self.generate_passage_text(&path),
+        }
+    }
+
+    fn pick_auto_source(&mut self) -> TextSource {
+        // Most variety first: the system dictionary, then the embedded list, then samples
+        if self
+            .load_system_dict_words()
+            .is_ok_and(|words| words.len() >= MIN_AUTO_SOURCE_WORDS)
+        {
+            TextSource::SystemDict
+        } else if self.load_google10k_words().len() >= MIN_AUTO_SOURCE_WORDS {
+            TextSource::Google10k
+        } else {
+            TextSource::Builtin
+        }
+    }
+
+    fn generate_builtin_text(&mut self) -> String {
+        let mut text = String::new();
+        let required_length = self.required_text_length();
+
+        // Generate enough text for the test duration
+        while text.len() < required_length {
+            let sample = &self.sample_texts[self.rng.gen_range(0..self.sample_texts.len())];
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(sample);
+        }
+
+        text
+    }
+
+    fn generate_google10k_text(&mut self) -> String {
+        let mut words = self.load_google10k_words();
+        if words.len() < MIN_KEY_FILTER_WORDS && self.relax_key_filter() {
+            words = self.load_google10k_words();
+        }
+        if words.is_empty() {
+            return self.generate_builtin_text(); // Fallback
+        }
+        self.generate_word_text(&words, true)
+    }
+
+    fn fits_key_filter(&self, word: &str) -> bool {
+        // --only-keys needs one of the keys in the word, --avoid-keys none of them
+        let has_any = |keys: &String| word.chars().any(|c| keys.contains(c));
+        self.only_keys.as_ref().is_none_or(has_any)
+            && !self.avoid_keys.as_ref().is_some_and(has_any)
+    }
+
+    fn relax_key_filter(&mut self) -> bool {
+        // Too few words would repeat endlessly, so the keys are dropped for the session
+        if self.only_keys.is_none() && self.avoid_keys.is_none() {
+            return false;
+        }
+        self.only_keys = None;
+        self.avoid_keys = None;
+        self.key_filter_ignored = true;
+        true
+    }
+
+    fn generate_system_dict_text(&mut self) -> String {
+        let mut result = self.load_system_dict_words();
+        if result
+            .as_ref()
+            .is_ok_and(|words| words.len() < MIN_KEY_FILTER_WORDS)
+            && self.relax_key_filter()
+        {
+            result = self.load_system_dict_words();
+        }
+        match result {
+            Ok(words) => {
+                if words.is_empty() {
+                    return self.generate_builtin_text(); // Fallback
+                }
+                self.generate_word_text(&words, false)
+            }
+            Err(e) => {
+                // Log warning and fallback to built-in texts if dictionary not available
+                eprintln!(
+                    "Warning: Could not load dictionary from {}: {}. Using built-in texts.",
+                    DICT_PATH, e
+                );
+                self.generate_builtin_text()
+            }
+        }
+    }
+
+    // `ranked` lists are most common first, for --frequency-weighted
+    fn generate_word_text(&mut self, words: &[String], ranked: bool) -> String {
+        let mut text = String::new();
+        let required_length = self.required_text_length();
+        // Never more than the list can satisfy, or re-rolling would never finish
+        let window = NO_REPEAT_WINDOW.min(words.len().saturating_sub(1));
+        let mut recent: VecDeque<usize> = VecDeque::with_capacity(window + 1);
+
+        // Words containing each letter, only needed when steering for coverage
+        let letter_words: Vec<Vec<usize>> = if self.cover_all_keys {
+            (b'a'..=b'z')
+                .map(|letter| {
+                    (0..words.len())
+                        .filter(|&i| words[i].contains(letter as char))
+                        .collect()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut letter_counts = [0usize; 26];
+
+        // Words weighted by how many slow bigrams they contain, for --drill-bigrams
+        let drill_words: Vec<(usize, usize)> = words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let score = self
+                    .slow_bigrams
+                    .iter()
+                    .filter(|bigram| word.contains(bigram.as_str()))
+                    .count();
+                (i, score)
+            })
+            .filter(|&(_, score)| score > 0)
+            .collect();
+        let drill_weights = WeightedIndex::new(drill_words.iter().map(|&(_, score)| score)).ok();
+
+        // Zipf's law: the word at rank r comes up about 1/r as often as the most common one
+        let rank_weights = if ranked && self.frequency_weighted {
+            WeightedIndex::new((1..=words.len()).map(|rank| 1.0 / rank as f64)).ok()
+        } else {
+            None
+        };
+
+        while text.len() < required_length {
+            // Re-roll words picked within the last few draws so the passage doesn't stutter
+            let mut index = self.draw_word_index(
+                words.len(),
+                &drill_words,
+                drill_weights.as_ref(),
+                rank_weights.as_ref(),
+            );
+            while recent.contains(&index) {
+                index = self.draw_word_index(
+                    words.len(),
+                    &drill_words,
+                    drill_weights.as_ref(),
+                    rank_weights.as_ref(),
+                );
+            }
+
+            // Until every letter the list can provide has appeared MIN_KEY_COVERAGE
+            // times, swap in a word with the scarcest one. This front-loads rare
+            // letters, so early passages are less like natural text. Recent words
+            // stay off limits; if they're the only ones with the letter, keep the draw.
+            let scarcest_letter = (0..letter_words.len())
+                .filter(|&letter| {
+                    letter_counts[letter] < MIN_KEY_COVERAGE && !letter_words[letter].is_empty()
+                })
+                .min_by_key(|&letter| letter_counts[letter]);
+            if let Some(letter) = scarcest_letter
+                && !words[index].contains((b'a' + letter as u8) as char)
+            {
+                let candidates: Vec<usize> = letter_words[letter]
+                    .iter()
+                    .copied()
+                    .filter(|candidate| !recent.contains(candidate))
+                    .collect();
+                if !candidates.is_empty() {
+                    index = candidates[self.rng.gen_range(0..candidates.len())];
+                }
+            }
+
+            recent.push_back(index);
+            if recent.len() > window {
+                recent.pop_front();
+            }
+
+            let word = &words[index];
+            for ch in word.chars().filter(|ch| ch.is_ascii_lowercase()) {
+                letter_counts[(ch as u8 - b'a') as usize] += 1;
+            }
+            let word = self.inject_symbols(word);
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(&word);
+        }
+
+        text
+    }
+
+    fn capitalize_words(&mut self, text: &str, rate: f64) -> String {
+        // Sentences always start with a capital; other words only some of the time
+        let mut result = String::with_capacity(text.len());
+        let mut at_word_start = true;
+        let mut sentence_start = true;
+        for c in text.chars() {
+            if at_word_start && c.is_alphabetic() && (sentence_start || self.rng.gen_bool(rate)) {
+                result.extend(c.to_uppercase());
+            } else {
+                result.push(c);
+            }
+            // Leading quotes and brackets don't count as the start of the word
+            if c.is_whitespace() {
+                at_word_start = true;
+            } else if c.is_alphanumeric() {
+                at_word_start = false;
+                sentence_start = false;
+            } else if matches!(c, '.' | '!' | '?') {
+                sentence_start = true;
+            }
+        }
+        result
+    }
+
+    fn inject_symbols(&mut self, word: &str) -> String {
+        // With both kinds enabled, a chosen word is equally likely to get either
+        if !(self.include_numbers || self.include_punctuation)
+            || !self.rng.gen_bool(self.injection_rate)
+        {
+            return word.to_string();
+        }
+        if self.include_numbers && (!self.include_punctuation || self.rng.gen_bool(0.5)) {
+            let digits = self.rng.gen_range(1..=MAX_INJECTED_DIGITS);
+            let number: String = (0..digits)
+                .map(|_| char::from(b'0' + self.rng.gen_range(0..10)))
+                .collect();
+            return format!("{} {}", word, number);
+        }
+        // Trailing marks are the common case, as in real text
+        if self.rng.gen_bool(0.75) {
+            let mark = TRAILING_PUNCTUATION[self.rng.gen_range(0..TRAILING_PUNCTUATION.len())];
+            format!("{}{}", word, mark)
+        } else {
+            let (open, close) =
+                WRAPPING_PUNCTUATION[self.rng.gen_range(0..WRAPPING_PUNCTUATION.len())];
+            format!("{}{}{}", open, word, close)
+        }
+    }
+
+    fn draw_word_index(
+        &mut self,
+        word_count: usize,
+        drill_words: &[(usize, usize)],
+        drill_weights: Option<&WeightedIndex<usize>>,
+        rank_weights: Option<&WeightedIndex<f64>>,
+    ) -> usize {
+        match (drill_weights, rank_weights) {
+            (Some(weights), _) if self.rng.gen_bool(DRILL_WORD_SHARE) => {
+                drill_words[weights.sample(&mut self.rng)].0
+            }
+            (_, Some(weights)) => weights.sample(&mut self.rng),
+            _ => self.rng.gen_range(0..word_count),
+        }
+    }
+
+    fn save_word_reviews(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.is_code_mode() {
+            return Ok(());
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // Only a missing file starts the schedule over; a broken one is left alone
+        let mut reviews = match load_word_reviews(self.profile.as_deref()) {
+            Ok(reviews) => reviews,
+            Err(e) if is_missing_file(e.as_ref()) => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        // Only words finished after warmup; the one cut off by the timer wasn't really tried
+        let words: Vec<(usize, usize)> = self
+            .get_typed_word_ranges()
+            .into_iter()
+            .filter(|&(start, end)| {
+                start >= self.stats_start_position && end <= self.current_position
+            })
+            .collect();
+        for range in words {
+            let word: String = self.target_chars[range.0..range.1].iter().collect();
+            let word = word
+                .trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase();
+            if word.is_empty() {
+                continue;
+            }
+
+            if self.word_had_error(range) {
+                let review = reviews.entry(word).or_default();
+                review.misses += 1;
+                review.interval_days = 0;
+                review.due = now;
+                review.last_seen = now;
+            } else if let Some(review) = reviews.get_mut(&word)
+                && review.due <= now
+            {
+                // Seeing a word again before it's due doesn't count as a review
+                review.interval_days = (review.interval_days * 2).max(1);
+                review.due = now + review.interval_days * SECS_PER_DAY as u64;
+                review.last_seen = now;
+                if review.interval_days > MAX_REVIEW_INTERVAL_DAYS {
+                    reviews.remove(&word);
+                }
+            }
+        }
+
+        let reviews_path = get_data_file_path(WORD_REVIEWS_FILENAME, self.profile.as_deref())?;
+        fs::write(reviews_path, serde_json::to_string(&reviews)?)?;
+        // The next test in this session reviews whatever is due now
+        if self.review {
+            self.review_words = due_words(&reviews, now);
+        }
+        Ok(())
+    }
+
+    fn save_bigram_stats(&self) -> Result<(), Box<dyn Error>> {
+        // Merge this run into the running totals from earlier sessions; a broken file is left alone
+        let mut all_stats = match load_bigram_stats(self.profile.as_deref()) {
+            Ok(stats) => stats,
+            Err(e) if is_missing_file(e.as_ref()) => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        for (bigram, stats) in &self.bigram_metrics {
+            let total = all_stats.entry(bigram.clone()).or_default();
+            total.total_ms += stats.total_ms;
+            total.count += stats.count;
+        }
+        let bigrams_path = get_data_file_path(BIGRAMS_FILENAME, self.profile.as_deref())?;
+        fs::write(bigrams_path, serde_json::to_string(&all_stats)?)?;
+        Ok(())
+    }
+
+    fn get_drill(&self) -> Option<String> {
+        // Review sessions take priority over the home-row list
+        (self.home_row && self.review_words.is_empty() && !self.is_code_mode())
+            .then(|| "home-row".to_string())
+    }
+
+    fn load_home_row_words(&mut self) -> Vec<String> {
+        // Words from the chosen list spelled only with the layout's home-row letters
+        let letters: Vec<char> = self.layout.rows()[1]
+            .chars()
+            .filter(|c| c.is_ascii_alphabetic())
+            .collect();
+        let source_words = match self.text_source {
+            TextSource::SystemDict => self.load_system_dict_words().unwrap_or_default(),
+            _ => self.load_google10k_words(),
+        };
+        let mut words: Vec<String> = source_words
+            .into_iter()
+            .filter(|word| word.chars().all(|c| letters.contains(&c)))
+            .collect();
+
+        while words.len() < MIN_HOME_ROW_WORDS {
+            let length = self.rng.gen_range(MIN_WORD_LENGTH..=self.max_word_length);
+            let word = (0..length)
+                .map(|_| letters[self.rng.gen_range(0..letters.len())])
+                .collect();
+            words.push(word);
+        }
+        words
+    }
+
+    fn load_google10k_words(&mut self) -> Vec<String> {
+        // Parsed once; restarts reuse the list unless the length limit changed
+        if self
+            .google_words
+            .as_ref()
+            .is_none_or(|(max_length, _)| *max_length != self.max_word_length)
+        {
+            let words = GOOGLE_10000_WORDS
+                .lines()
+                .filter(|line| {
+                    let word = line.trim();
+                    // Filter for reasonable words: MIN_WORD_LENGTH to max_word_length characters, only letters
+                    word.len() >= MIN_WORD_LENGTH
+                        && word.len() <= self.max_word_length
+                        && word.chars().all(|c| c.is_ascii_lowercase())
+                })
+                .map(|s| s.trim().to_string())
+                .collect();
+            self.google_words = Some((self.max_word_length, words));
+        }
+        self.key_filtered(self.google_words.as_ref())
+    }
+
+    fn load_system_dict_words(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        // Read from disk once; restarts reuse the list unless the length limit changed
+        if self
+            .dict_words
+            .as_ref()
+            .is_none_or(|(max_length, _)| *max_length != self.max_word_length)
+        {
+            let dict_content = fs::read_to_string(DICT_PATH)?;
+            let words = dict_content
+                .lines()
+                .filter(|line| {
+                    let word = line.trim();
+                    // Filter for reasonable words: MIN_WORD_LENGTH to max_word_length characters, only letters, no proper nouns
+                    word.len() >= MIN_WORD_LENGTH
+                        && word.len() <= self.max_word_length
+                        && word.chars().all(|c| c.is_ascii_lowercase())
+                })
+                .map(|s| s.trim().to_string())
+                .collect();
+            self.dict_words = Some((self.max_word_length, words));
+        }
+        Ok(self.key_filtered(self.dict_words.as_ref()))
+    }
+
+    fn key_filtered(&self, words: Option<&(usize, Vec<String>)>) -> Vec<String> {
+        // Applied on every load, since the key filters can be dropped mid-session
+        words.map_or_else(Vec::new, |(_, words)| {
+            words
+                .iter()
+                .filter(|word| self.fits_key_filter(word))
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn generate_file_text(&mut self, path: &PathBuf) -> String {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                let required_length = self.required_text_length();
+                self.extract_code_section(&content, required_length)
+            }
+            Err(e) => {
+                eprintln!(
+                    "Warning: Could not read file {}: {}. Using built-in texts.",
+                    path.display(),
+                    e
+                );
+                self.generate_builtin_text()
+            }
+        }
+    }
+
+    fn generate_passage_text(&mut self, path: &PathBuf) -> String {
+        // Reflowed onto one line like the other prose sources; a short file is typed once as is.
+        // Restarts read the file again, so a fixed file is picked up
+        self.passage_fallback = None;
+        let text = match fs::read_to_string(path) {
+            Ok(content) => content.split_whitespace().collect::<Vec<_>>().join(" "),
+            Err(_) => {
+                self.passage_fallback = Some("file unreadable");
+                return self.generate_builtin_text();
+            }
+        };
+        if text.is_empty() {
+            self.passage_fallback = Some("file empty");
+            return self.generate_builtin_text();
+        }
+        text
+    }
+
+    fn extract_code_section(&mut self, content: &str, required_length: usize) -> String {
+        // Extract meaningful code sections (functions, methods, etc.)
+        let mut sections = Vec::new();
+        let mut current_section = String::new();
+        let mut in_function = false;
+        let mut brace_count = 0;
+
+        for line in content.lines() {
+            let trimmed = line.trim();
+            let line_indent = line.chars().take_while(|&c| c == ' ' || c == '\t').count();
+
+            // Detect function/method start for various languages
+            if !in_function
+                && (trimmed.starts_with("fn ") ||         // Rust
+                trimmed.starts_with("def ") ||        // Python
+                trimmed.starts_with("function ") ||   // JavaScript
+                trimmed.starts_with("func ") ||       // Go
+                // Better OCaml function detection - must be at top level and have parameters or be recursive
+                (line_indent == 0 && trimmed.starts_with("let ") && 
+                 (trimmed.contains("(") || trimmed.starts_with("let rec "))) ||
+                trimmed.starts_with("public ") ||     // Java/C#
+                trimmed.starts_with("private ") ||    // Java/C#
+                trimmed.starts_with("protected ") ||  // Java/C#
+                trimmed.contains("fn(") ||            // Rust closures
+                trimmed.contains("=>") ||             // JS arrow functions
+                (trimmed.contains("(") && trimmed.contains(")") && trimmed.contains("{")))
+            {
+                in_function = true;
+                current_section.clear();
+            }
+
+            if in_function {
+                current_section.push_str(line);
+                current_section.push('\n');
+
+                // Track braces for languages that use them
+                brace_count += line.matches('{').count() as i32;
+                brace_count -= line.matches('}').count() as i32;
+
+                // Detect end of function for brace-based languages
+                if brace_count == 0 && line.contains('}') {
+                    if current_section.len() >= 100 {
+                        // Only keep meaningful sections
+                        sections.push(current_section.clone());
+                    }
+                    current_section.clear();
+                    in_function = false;
+                    brace_count = 0;
+                }
+
+                // For Python and OCaml, detect based on indentation and empty lines
+                if brace_count == 0 && (
+                    // Empty line after function content
+                    (trimmed.is_empty() && current_section.trim().len() >= 50) ||
+                    // Another top-level definition (at indent 0)
+                    (!trimmed.is_empty() && line_indent == 0 && 
+                     (trimmed.starts_with("let ") || trimmed.starts_with("def ") || 
+                      trimmed.starts_with("class ") || trimmed.starts_with("type ") ||
+                      trimmed.starts_with("module ") || trimmed.starts_with("(*")))
+                ) {
+                    if current_section.len() >= 50 {
+                        sections.push(current_section.clone());
+                    }
+                    current_section.clear();
+                    in_function = false;
+                    
+                    // If we hit another function definition, start processing it
+                    if !trimmed.is_empty() && line_indent == 0 && 
+                       trimmed.starts_with("let ") && 
+                       (trimmed.contains("(") || trimmed.starts_with("let rec ")) {
+                        in_function = true;
+                        current_section.push_str(line);
+                        current_section.push('\n');
+                    }
+                }
+            }
+        }
+
+        // Don't forget the last section
+        if in_function && current_section.len() >= 50 {
+            sections.push(current_section);
+        }
+
+        // If no functions found, fall back to using chunks of the file
+        if sections.is_empty() {
+            let lines: Vec<&str> = content.lines().collect();
+            let chunk_size = 15; // Lines per chunk
+
+            for chunk in lines.chunks(chunk_size) {
+                let section = chunk.join("\n");
+                if section.trim().len() >= 50 {
+                    sections.push(section);
+                }
+            }
+        }
+
+        if sections.is_empty() {
+            // If still no sections, just use the whole content
+            return content.chars().take(required_length).collect();
+        }
+
+        // Ensure we have enough content by combining/repeating sections as needed
+        let rng = &mut self.rng;
+        let mut result = String::new();
+        let start_idx = rng.gen_range(0..sections.len());
+        let mut current_idx = start_idx;
+        let mut iterations = 0;
+        const MAX_ITERATIONS: usize = 100; // Prevent infinite loops
+        
+        while result.len() < required_length && iterations < MAX_ITERATIONS {
+            if !result.is_empty() {
+                result.push_str("\n\n"); // Add spacing between sections
+            }
+            
+            result.push_str(&sections[current_idx]);
+            
+            // Move to next section (cycle through all sections)
+            current_idx = (current_idx + 1) % sections.len();
+            iterations += 1;
+            
+            // If we've gone through all sections once and still need more content,
+            // continue cycling but add some randomization
+            if current_idx == start_idx && result.len() < required_length {
+                current_idx = rng.gen_range(0..sections.len());
+            }
+        }
+        
+        // If we somehow have too much content, truncate at a reasonable boundary
+        if result.len() > required_length * 2 {
+            // Try to truncate at a line boundary
+            let truncated = result.chars().take(required_length).collect::<String>();
+            if let Some(last_newline) = truncated.rfind('\n') {
+                truncated[..last_newline].to_string()
+            } else {
+                truncated
+            }
+        } else {
+            result
+        }
+    }
+
+    fn start_if_needed(&mut self) {
+        if self.start_time.is_none() && self.warmup_start.is_none() {
+            self.reaction_time = self.ready_time.map(|ready| ready.elapsed());
+            if self.warmup_duration.is_zero() && self.launch_gate.is_none() {
+                self.begin_recording();
+            } else {
+                self.warmup_start = Some(Instant::now());
+                self.start_timing_current_key();
+            }
+        }
+        self.check_warmup();
+    }
+
+    fn skip_word(&mut self) {
+        if self.is_finished {
+            return;
+        }
+
+        self.start_if_needed();
+        if self.keylog.is_some() {
+            self.record_keystroke(KEYLOG_SKIP, Instant::now());
+        }
+
+        // Skipped characters count as errors, but no key timing is recorded for them
+        while self.current_position < self.target_chars.len() {
+            let target_char = self.target_chars[self.current_position];
+            if target_char.is_whitespace() {
+                break;
+            }
+            self.user_input.push(SKIPPED_CHAR_MARKER);
+            self.errors += 1;
+            self.current_streak = 0;
+            self.total_keystrokes += 1;
+            if self.current_position < self.correction_attempts.len() {
+                self.correction_attempts[self.current_position] = true;
+            }
+            self.current_position += 1;
+        }
+
+        // Consume the separator so the cursor lands at the start of the next word
+        if self.current_position < self.target_chars.len() {
+            let separator = self.target_chars[self.current_position];
+            self.user_input.push(separator);
+            self.current_position += 1;
+            if separator == '\n' {
+                self.skip_leading_whitespace();
+            }
+        }
+
+        self.last_keystroke_time = Some(Instant::now());
+        self.start_timing_current_key();
+
+        if self.is_passage_complete() {
+            self.finish();
+        }
+        self.record_typed_times();
+        self.extend_zen_text();
+    }
+
+    fn record_typed_times(&mut self) {
+        // typed_times[i] is when position i was last passed, for the replay
+        let elapsed = self.get_elapsed_time();
+        self.typed_times.truncate(self.current_position);
+        self.typed_times.resize(self.current_position, elapsed);
+    }
+
+    fn get_burst_wpm(&self, window: Duration) -> Option<f64> {
+        // Most correct characters typed within any `window`-long stretch of the run
+        if self.get_test_length() < window {
+            return None;
+        }
+        let user_chars: Vec<char> = self.user_input.chars().collect();
+        let correct_times: Vec<Duration> = (self.stats_start_position..self.typed_times.len())
+            .filter(|&i| user_chars.get(i) == self.target_chars.get(i))
+            .map(|i| self.typed_times[i])
+            .collect();
+
+        let mut best = 0;
+        let mut start = 0;
+        for (end, &time) in correct_times.iter().enumerate() {
+            while time - correct_times[start] > window {
+                start += 1;
+            }
+            best = best.max(end - start + 1);
+        }
+
+        let minutes = window.as_secs_f64() / 60.0;
+        Some(best as f64 / self.chars_per_word / minutes)
+    }
+
+    fn get_replay_delays(&self) -> Vec<Duration> {
+        // Time spent reaching each position since the one before it
+        let mut previous = Duration::ZERO;
+        self.typed_times
+            .iter()
+            .map(|&time| {
+                let delay = time.saturating_sub(previous);
+                previous = time;
+                delay
+            })
+            .collect()
+    }
+
+    fn handle_key_event(&mut self, key: KeyCode) {
+        if self.is_finished {
+            return;
+        }
+
+        self.start_if_needed();
+
+        let now = Instant::now();
+
+        if self.keylog.is_some() {
+            match key {
+                KeyCode::Char(c) => self.record_keystroke(c, now),
+                KeyCode::Enter => self.record_keystroke('\n', now),
+                KeyCode::Backspace => self.record_keystroke(KEYLOG_BACKSPACE, now),
+                _ => {}
+            }
+        }
+
+        match key {
+            KeyCode::Enter if self.current_position < self.target_chars.len() => {
+                // Handle Enter key for newlines in code mode
+                let target_char = self.target_chars[self.current_position];
+                
+                if target_char == '\n' {
+                    // Record timing data for the newline
+                    if let Some(key_start_time) = self.current_key_start_time {
+                        let key_response_time = now.duration_since(key_start_time);
+                        self.key_metrics
+                            .entry(target_char)
+                            .or_insert_with(KeyMetrics::new)
+                            .times
+                            .push(key_response_time);
+                    }
+                    self.record_streak(true);
+                    
+                    if self.require_correction {
+                        // In correction mode, treat Enter like any correct character
+                        self.user_input.push('\n');
+                        self.total_keystrokes += 1;
+                        self.current_position += 1;
+                        
+                        // Skip leading whitespace after newline in code mode
+                        self.skip_leading_whitespace();
+                        
+                        self.start_timing_current_key();
+                        self.update_wpm();
+                    } else {
+                        // In normal mode
+                        self.user_input.push('\n');
+                        self.total_keystrokes += 1;
+                        self.current_position += 1;
+                        
+                        // Skip leading whitespace after newline in code mode
+                        self.skip_leading_whitespace();
+                        
+                        self.start_timing_current_key();
+                        self.update_wpm();
+                    }
+                    
+                    self.last_keystroke_time = Some(now);
+                    
+                    if self.is_passage_complete() {
+                        self.finish();
+                    }
+                } else {
+                    // Wrong key - Enter pressed when not expecting newline
+                    self.record_error_kind('\n');
+                    self.record_streak(false);
+                    let space_error = target_char == ' ';
+                    if space_error {
+                        self.space_errors += 1;
+                    }
+                    if self.require_correction
+                        || self.error_behavior == ErrorBehavior::Stay
+                        || (self.strict_space && space_error)
+                    {
+                        self.errors += 1;
+                        self.total_keystrokes += 1;
+                        if self.current_position < self.correction_attempts.len() {
+                            self.correction_attempts[self.current_position] = true;
+                            self.retry_counts[self.current_position] += 1;
+                        }
+                    } else {
+                        // In normal mode, treat it as an error but continue
+                        self.user_input.push('\n'); // Show what was typed
+                        self.errors += 1;
+                        self.total_keystrokes += 1;
+                        if self.current_position < self.correction_attempts.len() {
+                            self.correction_attempts[self.current_position] = true;
+                        }
+                        self.current_position += 1;
+                        self.start_timing_current_key();
+                    }
+                }
+            }
+            KeyCode::Char(c) if self.current_position < self.target_chars.len() => {
+                let target_char = self.target_chars[self.current_position];
+
+                // Record timing data only when we get the target character (correct or as an attempt)
+                if let Some(key_start_time) = self.current_key_start_time {
+                    let key_response_time = now.duration_since(key_start_time);
+                    // Always record timing for target character attempts
+                    self.key_metrics
+                        .entry(target_char)
+                        .or_insert_with(KeyMetrics::new)
+                        .times
+                        .push(key_response_time);
+
+                    // Letter-to-letter transitions typed correctly feed the bigram drill;
+                    // after a typo the time is from the wrong key, not the letter before
+                    if c == target_char
+                        && self.current_position > self.stats_start_position
+                        && let Some(&previous) = self.target_chars.get(self.current_position - 1)
+                        && self.user_input.ends_with(previous)
+                        && previous.is_alphabetic()
+                        && target_char.is_alphabetic()
+                    {
+                        let bigram: String = [previous, target_char].iter().collect();
+                        let stats = self
+                            .bigram_metrics
+                            .entry(bigram.to_lowercase())
+                            .or_default();
+                        stats.total_ms += key_response_time.as_millis() as u64;
+                        stats.count += 1;
+                    }
+                }
+
+                // A missed or extra space shifts every word after it
+                let space_error = c != target_char && (c == ' ' || target_char == ' ');
+                if space_error {
+                    self.space_errors += 1;
+                }
+
+                if self.require_correction {
+                    // In correction mode, only accept the correct character
+                    if c == target_char {
+                        self.record_streak(true);
+                        self.user_input.push(c);
+                        self.total_keystrokes += 1;
+                        self.current_position += 1;
+                        self.start_timing_current_key(); // Start timing next key
+                        self.update_wpm();
+                    } else {
+                        // Wrong character - mark this position as needing correction and track error
+                        self.reject_char(c, target_char);
+                        // Don't start timing next key yet - stay on current key until correct
+                    }
+                } else if self.strict_space && space_error {
+                    // --strict-space holds the cursor like correction mode, even over word skipping
+                    self.reject_char(c, target_char);
+                } else if self.error_behavior == ErrorBehavior::Stay && c != target_char {
+                    if c == ' ' && !target_char.is_whitespace() {
+                        // Space gives up on the rest of the word instead of retrying
+                        self.skip_word();
+                    } else {
+                        // Like correction mode, the cursor waits for the right character
+                        self.reject_char(c, target_char);
+                    }
+                } else {
+                    // In normal mode, allow proceeding with errors
+                    self.user_input.push(c);
+                    self.total_keystrokes += 1;
+
+                    if c == target_char {
+                        self.record_streak(true);
+                        self.current_position += 1;
+                        self.start_timing_current_key(); // Start timing next key
+                        self.update_wpm(); // Only update WPM on correct characters
+                    } else {
+                        self.errors += 1;
+                        self.record_streak(false);
+                        self.record_error_kind(c);
+                        self.key_metrics
+                            .entry(target_char)
+                            .or_insert_with(KeyMetrics::new)
+                            .errors += 1;
+                        // Mark this position as having had an error
+                        if self.current_position < self.correction_attempts.len() {
+                            self.correction_attempts[self.current_position] = true;
+                        }
+                        self.current_position += 1; // Move forward even with errors
+                        self.start_timing_current_key(); // Start timing next key
+                    }
+                }
+
+                self.last_keystroke_time = Some(now);
+
+                if self.is_passage_complete() {
+                    self.finish();
+                }
+            }
+            KeyCode::Tab
+                if self.is_code_mode() && self.current_position < self.target_chars.len() =>
+            {
+                // Tab types a tab, or up to TAB_WIDTH spaces the way an editor expands it
+                let spaces = self.target_chars[self.current_position..]
+                    .iter()
+                    .take(TAB_WIDTH)
+                    .take_while(|&&ch| ch == ' ')
+                    .count();
+                if spaces == 0 {
+                    self.handle_key_event(KeyCode::Char('\t'));
+                    return;
+                }
+
+                // Still one press: one keystroke and one timing, however many spaces it covers
+                if self.keylog.is_some() {
+                    self.record_keystroke('\t', now);
+                }
+                if let Some(key_start_time) = self.current_key_start_time {
+                    self.key_metrics
+                        .entry(' ')
+                        .or_insert_with(KeyMetrics::new)
+                        .times
+                        .push(now.duration_since(key_start_time));
+                }
+                self.record_streak(true);
+                self.user_input.extend(std::iter::repeat_n(' ', spaces));
+                self.total_keystrokes += 1;
+                self.current_position += spaces;
+                self.start_timing_current_key();
+                self.update_wpm();
+                self.last_keystroke_time = Some(now);
+
+                if self.is_passage_complete() {
+                    self.finish();
+                }
+            }
+            // Word passages have no tabs, so Tab is ignored rather than counted as an error
+            KeyCode::Tab => {}
+            KeyCode::Backspace => {
+                if self.delete_last_grapheme() {
+                    self.total_keystrokes += 1;
+                }
+                self.last_keystroke_time = Some(now);
+            }
+            _ => {}
+        }
+
+        self.record_word_typos();
+        self.record_typed_times();
+        self.extend_zen_text();
+    }
+
+    fn delete_last_grapheme(&mut self) -> bool {
+        // Remove a whole grapheme cluster so combining marks and emoji
+        // sequences are deleted the way they're displayed
+        let Some((start, cluster)) = self.user_input.grapheme_indices(true).next_back() else {
+            return false;
+        };
+        let cluster_chars = cluster.chars().count();
+        self.user_input.truncate(start);
+        if self.current_position > 0 {
+            self.current_position = self.current_position.saturating_sub(cluster_chars);
+            self.start_timing_current_key(); // Start timing the key we're now on
+        }
+        true
+    }
+
+    fn delete_word(&mut self) {
+        if self.is_finished || self.current_position == 0 {
+            return;
+        }
+        let now = Instant::now();
+        if self.keylog.is_some() {
+            self.record_keystroke(KEYLOG_DELETE_WORD, now);
+        }
+
+        // Back to the start of the word, or of the one before if the cursor is already there.
+        // Like single Backspaces, the positions keep their error marks
+        let mut word_start = self.current_position;
+        while word_start > 0 && self.target_chars[word_start - 1].is_whitespace() {
+            word_start -= 1;
+        }
+        while word_start > 0 && !self.target_chars[word_start - 1].is_whitespace() {
+            word_start -= 1;
+        }
+        let mut deleted = false;
+        while self.current_position > word_start && self.delete_last_grapheme() {
+            deleted = true;
+        }
+
+        // One keystroke, however many characters it took back
+        if deleted {
+            self.total_keystrokes += 1;
+        }
+        self.last_keystroke_time = Some(now);
+        self.record_typed_times();
+    }
+
+    fn record_word_typos(&mut self) {
+        // Compare each word against what was typed once the cursor moves past it
+        let user_chars: Vec<char> = self.user_input.chars().collect();
+        let checked_from = self.typo_checked_position.max(self.stats_start_position);
+        for (start, end) in self.get_typed_word_ranges() {
+            if start < checked_from || end > self.current_position {
+                continue;
+            }
+            self.typo_checked_position = end;
+            let Some(typed) = user_chars.get(start..end) else {
+                continue;
+            };
+            // Skipped words weren't attempted, so there's no spelling to learn from
+            if typed.contains(&SKIPPED_CHAR_MARKER) {
+                continue;
+            }
+            let typed: String = (start..end)
+                .zip(typed)
+                .map(|(i, &ch)| self.rejected_chars.get(&i).copied().unwrap_or(ch))
+                .collect();
+            let word: String = self.target_chars[start..end].iter().collect();
+            let typed = typed.trim_matches(|c: char| !c.is_alphanumeric());
+            let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+            if typed.is_empty() || typed == word {
+                continue;
+            }
+            *self
+                .word_typos
+                .entry(word.to_string())
+                .or_default()
+                .entry(typed.to_string())
+                .or_insert(0) += 1;
+        }
+    }
+
+    fn get_common_typos(&self, count: usize) -> Vec<(String, String, usize)> {
+        // (typed, word, times), most repeated first
+        let mut typos: Vec<(String, String, usize)> = self
+            .word_typos
+            .iter()
+            .flat_map(|(word, typed)| {
+                typed
+                    .iter()
+                    .map(move |(typed, &times)| (typed.clone(), word.clone(), times))
+            })
+            .collect();
+        typos.sort_by(|a, b| {
+            b.2.cmp(&a.2)
+                .then_with(|| a.1.cmp(&b.1))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        typos.truncate(count);
+        typos
+    }
+
+    fn record_keystroke(&mut self, typed: char, now: Instant) {
+        // Warmup keystrokes are left out like everywhere else
+        if let Some(start) = self.start_time
+            && let Some(&target) = self.target_chars.get(self.current_position)
+        {
+            self.keystrokes.push(Keystroke {
+                elapsed_ms: now.duration_since(start).as_millis() as u64,
+                target,
+                typed,
+                response_ms: self
+                    .current_key_start_time
+                    .map(|key_start| now.duration_since(key_start).as_millis() as u64),
+            });
+        }
+    }
+
+    fn write_keylog(&self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        // Overwritten by each test, so the file always holds the latest run
+        let mut file = fs::File::create(path)?;
+        writeln!(file, "{}", KEYLOG_HEADER)?;
+        for keystroke in &self.keystrokes {
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                keystroke.elapsed_ms,
+                csv_char(keystroke.target),
+                csv_char(keystroke.typed),
+                keystroke.typed == keystroke.target,
+                keystroke
+                    .response_ms
+                    .map_or(String::new(), |ms| ms.to_string())
+            )?;
+        }
+        Ok(())
+    }
+
+    fn replay_keylog(&mut self, keystrokes: &[Keystroke]) -> Result<(), String> {
+        // Time is simulated rather than waited out, so a run replays instantly
+        let mut previous_ms = 0;
+        for (index, keystroke) in keystrokes.iter().enumerate() {
+            self.shift_clock(Duration::from_millis(
+                keystroke.elapsed_ms.saturating_sub(previous_ms),
+            ));
+            previous_ms = keystroke.elapsed_ms;
+            self.check_time_limit();
+            if self.is_finished {
+                return Err(format!(
+                    "Test ended before keystroke {} of {}",
+                    index + 1,
+                    keystrokes.len()
+                ));
+            }
+            if self.target_chars.get(self.current_position) != Some(&keystroke.target) {
+                return Err(format!(
+                    "Keystroke {} was typed at '{}' but the passage has '{}' there; use the recorded test's --seed and options",
+                    index + 1,
+                    keystroke.target.escape_default(),
+                    self.target_chars
+                        .get(self.current_position)
+                        .map_or(String::new(), |ch| ch.escape_default().to_string())
+                ));
+            }
+            match keystroke.typed {
+                '\n' => self.handle_key_event(KeyCode::Enter),
+                KEYLOG_BACKSPACE => self.handle_key_event(KeyCode::Backspace),
+                // A tab press may have stood for several spaces
+                '\t' => self.handle_key_event(KeyCode::Tab),
+                KEYLOG_DELETE_WORD => self.delete_word(),
+                KEYLOG_SKIP => self.skip_word(),
+                typed => self.handle_key_event(KeyCode::Char(typed)),
+            }
+        }
+
+        // A timed test that ran out of keystrokes ended when the clock did
+        if !self.is_finished && self.mode == TestMode::Timed {
+            self.shift_clock(self.test_duration.saturating_sub(self.get_elapsed_time()));
+            self.check_time_limit();
+        }
+        if !self.is_finished {
+            self.finish();
+        }
+        Ok(())
+    }
+
+    fn shift_clock(&mut self, by: Duration) {
+        // Moving every timestamp back is the same as that much time passing
+        for instant in [
+            &mut self.start_time,
+            &mut self.last_wpm_update,
+            &mut self.current_key_start_time,
+            &mut self.last_keystroke_time,
+        ] {
+            if let Some(earlier) = instant.and_then(|time| time.checked_sub(by)) {
+                *instant = Some(earlier);
+            }
+        }
+    }
+
+    fn get_bench_report(&self) -> String {
+        // Full precision, so metric changes show up when diffing two reports
+        [
+            format!("Duration: {:.2}s", self.get_test_length().as_secs_f64()),
+            format!("Average WPM: {:.2}", self.get_average_wpm()),
+            format!("Peak WPM: {:.2}", self.get_peak_wpm()),
+            format!("Accuracy: {:.2}%", self.get_accuracy()),
+            format!("Characters: {}", self.get_characters_typed()),
+            format!("Errors: {}", self.errors),
+            format!("Keystrokes: {}", self.total_keystrokes),
+            format!("Best Streak: {}", self.best_streak),
+        ]
+        .join("\n")
+    }
+
+    fn ring_bell(&mut self) {
+        // A burst of mistakes rings once rather than once per key
+        let now = Instant::now();
+        if self.bell_on_error
+            && self.last_bell.is_none_or(|last| {
+                now.duration_since(last) >= Duration::from_millis(BELL_INTERVAL_MS)
+            })
+        {
+            self.bell_pending = true;
+            self.last_bell = Some(now);
+        }
+    }
+
+    fn is_key_repeat(&mut self, key: KeyCode) -> bool {
+        let KeyCode::Char(c) = key else {
+            return false;
+        };
+        if !self.debounce {
+            return false;
+        }
+        let now = Instant::now();
+        let held = self.last_char_event.is_some_and(|(last, at)| {
+            last == c && now.duration_since(at) < Duration::from_millis(DEBOUNCE_MS)
+        });
+        // A doubled letter in the passage is typed legitimately, however fast
+        let doubled = self.target_chars.get(self.current_position) == Some(&c);
+        self.last_char_event = Some((c, now));
+        held && !doubled
+    }
+
+    fn get_pacer_position(&self) -> Option<usize> {
+        // Where a typist at exactly the pacer's speed would be, counted from when stats began
+        let wpm = self.pacer? as f64;
+        self.start_time?;
+        let minutes = self.get_elapsed_time().as_secs_f64() / 60.0;
+        Some(self.stats_start_position + (minutes * wpm * self.chars_per_word) as usize)
+    }
+
+    fn record_streak(&mut self, correct: bool) {
+        if correct {
+            self.current_streak += 1;
+            self.best_streak = self.best_streak.max(self.current_streak);
+        } else {
+            self.current_streak = 0;
+        }
+    }
+
+    fn get_report_stats(&self) -> Vec<(&'static str, String)> {
+        let mut stats = vec![
+            ("Average WPM", format!("{:.1}", self.get_average_wpm())),
+            ("Peak WPM", format!("{:.1}", self.get_peak_wpm())),
+            ("Accuracy", format!("{:.1}%", self.get_accuracy())),
+            ("Characters Typed", self.get_characters_typed().to_string()),
+            ("Errors", self.errors.to_string()),
+            ("Best Streak", self.best_streak.to_string()),
+            (
+                "Test Duration",
+                format!("{}s", self.get_test_length().as_secs()),
+            ),
+            ("Mode", self.mode.to_string()),
+            ("Text Source", self.text_source.to_string()),
+            ("Seed", self.seed.to_string()),
+        ];
+        if let Some(burst_wpm) = self.get_burst_wpm(self.burst_window) {
+            stats.insert(2, ("Burst WPM", format!("{:.1}", burst_wpm)));
+        }
+        if let Some((key, required)) = self.required_key_accuracy {
+            let result = match self.get_key_accuracy(key) {
+                Some(accuracy) if accuracy >= required => format!("{:.1}% PASSED", accuracy),
+                Some(accuracy) => format!("{:.1}% FAILED (needs {}%)", accuracy, required),
+                None => "Not typed".to_string(),
+            };
+            stats.push((
+                "Key Accuracy",
+                format!("'{}' {}", key.escape_default(), result),
+            ));
+        }
+        stats
+    }
+
+    fn write_markdown_report(&self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        let mut report = format!("# Ratatype result, {}\n\n", format_day(today()));
+        report.push_str("| Stat | Value |\n| --- | --- |\n");
+        for (name, value) in self.get_report_stats() {
+            report.push_str(&format!("| {} | {} |\n", name, markdown_cell(&value)));
+        }
+
+        let key_sections = [
+            ("Slowest Keys", self.get_slowest_keys(REPORT_KEY_COUNT)),
+            ("Fastest Keys", self.get_fastest_keys(REPORT_KEY_COUNT)),
+        ];
+        for (title, keys) in key_sections {
+            report.push_str(&format!("\n## {}\n\n", title));
+            if keys.is_empty() {
+                report.push_str("No key timings recorded.\n");
+                continue;
+            }
+            report.push_str("| Key | Time (ms) |\n| --- | --- |\n");
+            for (key, time) in keys {
+                let key = match key {
+                    ' ' => "space".to_string(),
+                    key => format!("`{}`", key.escape_default()),
+                };
+                report.push_str(&format!(
+                    "| {} | {} |\n",
+                    markdown_cell(&key),
+                    time.as_millis()
+                ));
+            }
+        }
+
+        let typed: String = self
+            .target_chars
+            .iter()
+            .take(self.current_position)
+            .collect();
+        if self.export_md_passage && !typed.is_empty() {
+            report.push_str(&format!("\n## Passage\n\n```text\n{}\n```\n", typed));
+        }
+
+        fs::write(path, report)?;
+        Ok(())
+    }
+
+    fn write_text_report(&self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        // Plain text that survives being pasted into a chat or a log file
+        let key_list = |keys: Vec<(char, Duration)>| {
+            if keys.is_empty() {
+                return "-".to_string();
+            }
+            keys.into_iter()
+                .map(|(key, time)| match key {
+                    ' ' => format!("space {}ms", time.as_millis()),
+                    key => format!("'{}' {}ms", key.escape_default(), time.as_millis()),
+                })
+                .collect::<Vec<_>>()
+                .join(" · ")
+        };
+        let wpm: Vec<f64> = self
+            .get_graph_data_points()
+            .iter()
+            .map(|&(_, wpm)| wpm)
+            .collect();
+        let graph = match sparkline(&wpm) {
+            Some((line, low, high)) => format!("{} ({:.0} to {:.0})", line, low, high),
+            None => "-".to_string(),
+        };
+
+        let slowest = key_list(self.get_slowest_keys(REPORT_KEY_COUNT));
+        let fastest = key_list(self.get_fastest_keys(REPORT_KEY_COUNT));
+        let mut rows = self.get_report_stats();
+        rows.extend([
+            ("Slowest Keys", slowest),
+            ("Fastest Keys", fastest),
+            ("WPM Graph", graph),
+        ]);
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+        let mut report = format!("Ratatype result, {}\n\n", format_day(today()));
+        for (name, value) in rows {
+            report.push_str(&format!("{:<width$}  {}\n", name, value));
+        }
+
+        fs::write(path, report)?;
+        Ok(())
+    }
+
+    fn reject_char(&mut self, typed: char, target_char: char) {
+        self.rejected_chars
+            .entry(self.current_position)
+            .or_insert(typed);
+        self.errors += 1;
+        self.record_streak(false);
+        self.total_keystrokes += 1;
+        self.record_error_kind(typed);
+        self.key_metrics
+            .entry(target_char)
+            .or_insert_with(KeyMetrics::new)
+            .errors += 1;
+        if self.current_position < self.correction_attempts.len() {
+            self.correction_attempts[self.current_position] = true;
+            self.retry_counts[self.current_position] += 1;
+        }
+    }
+
+    fn record_error_kind(&mut self, typed: char) {
+        self.ring_bell();
+        let position = self.current_position;
+        let target = self.target_chars[position];
+        let previous_target = position
+            .checked_sub(1)
+            .and_then(|i| self.target_chars.get(i));
+        let previous_typed = position
+            .checked_sub(1)
+            .and_then(|i| self.user_input.chars().nth(i));
+        let next_target = self.target_chars.get(position + 1);
+
+        let kind = if typed.to_lowercase().eq(target.to_lowercase()) {
+            ErrorKind::Case
+        } else if next_target == Some(&typed)
+            || (previous_target == Some(&typed) && previous_typed == Some(target))
+        {
+            // "teh" for "the": both the early 'e' and the late 'h' are one swap
+            ErrorKind::Transposition
+        } else if previous_target == Some(&typed) {
+            ErrorKind::Insertion
+        } else if self.layout.are_adjacent(typed, target) {
+            ErrorKind::AdjacentKey
+        } else {
+            ErrorKind::Other
+        };
+        *self.error_kinds.entry(kind).or_insert(0) += 1;
+    }
+
+    fn update_wpm(&mut self) {
+        if self.in_overtime && self.finish_word == Some(Overtime::Exclude) {
+            return;
+        }
+        if let Some(start) = self.start_time {
+            let now = Instant::now();
+            let elapsed_seconds = start.elapsed().as_secs_f64();
+
+            // Only update WPM once a sample interval has passed since last update
+            // and the --wpm-delay has passed since start (to avoid huge initial values)
+            let should_update = if let Some(last_update) = self.last_wpm_update {
+                now.duration_since(last_update).as_secs_f64() >= self.sample_interval
+            } else {
+                elapsed_seconds >= self.wpm_delay
+            };
+
+            if should_update && elapsed_seconds >= self.wpm_delay {
+                let elapsed_minutes = elapsed_seconds / 60.0;
+                let words_typed = self.get_characters_typed() as f64 / self.chars_per_word;
+                let wpm = words_typed / elapsed_minutes;
+
+                // Cap the WPM at reasonable maximum
+                let capped_wpm = wpm.min(self.max_wpm);
+                let net_wpm = ((words_typed - self.get_uncorrected_errors() as f64)
+                    / elapsed_minutes)
+                    .clamp(0.0, self.max_wpm);
+
+                self.wpm_history.push(capped_wpm);
+                self.wpm_data_points.push((elapsed_seconds, capped_wpm));
+                self.raw_wpm_history.push(wpm);
+                self.raw_wpm_data_points.push((elapsed_seconds, wpm));
+                self.net_wpm_history.push(net_wpm);
+                self.last_wpm_update = Some(now);
+                #[cfg(feature = "broadcast")]
+                self.publish_live_stats();
             }
-            Err(e) => {
-                // Log warning and fallback to built-in texts if dictionary not available
-                eprintln!(
-                    "Warning: Could not load dictionary from {}: {}. Using built-in texts.",
-                    DICT_PATH, e
-                );
-                self.generate_builtin_text()
+        }
+    }
+
+    #[cfg(feature = "broadcast")]
+    fn publish_live_stats(&self) {
+        if let Some(live_stats) = &self.live_stats
+            && let Ok(mut stats) = live_stats.lock()
+        {
+            *stats = LiveStats {
+                wpm: self.get_current_wpm(),
+                accuracy: self.get_accuracy(),
+                errors: self.errors,
+                elapsed_seconds: self.get_elapsed_time().as_secs_f64(),
+                finished: self.is_finished,
+            };
+        }
+    }
+
+    fn get_current_wpm(&self) -> f64 {
+        self.wpm_history.last().copied().unwrap_or(0.0)
+    }
+
+    fn get_net_wpm(&self) -> f64 {
+        self.net_wpm_history.last().copied().unwrap_or(0.0)
+    }
+
+    fn get_average_net_wpm(&self) -> f64 {
+        if self.net_wpm_history.is_empty() {
+            0.0
+        } else {
+            self.net_wpm_history.iter().sum::<f64>() / self.net_wpm_history.len() as f64
+        }
+    }
+
+    fn get_uncorrected_errors(&self) -> usize {
+        // Typed characters since stats began that are still wrong
+        self.user_input
+            .chars()
+            .zip(&self.target_chars)
+            .take(self.current_position)
+            .skip(self.stats_start_position)
+            .filter(|(typed, target)| typed != *target)
+            .count()
+    }
+
+    fn get_stats_wpm_history(&self) -> &[f64] {
+        if self.uncapped_graph {
+            &self.raw_wpm_history
+        } else {
+            &self.wpm_history
+        }
+    }
+
+    fn get_graph_data_points(&self) -> &[(f64, f64)] {
+        if self.uncapped_graph {
+            &self.raw_wpm_data_points
+        } else {
+            &self.wpm_data_points
+        }
+    }
+
+    fn get_average_wpm(&self) -> f64 {
+        let history = self.get_stats_wpm_history();
+        if history.is_empty() {
+            0.0
+        } else {
+            history.iter().sum::<f64>() / history.len() as f64
+        }
+    }
+
+    fn get_peak_wpm(&self) -> f64 {
+        self.get_stats_wpm_history()
+            .iter()
+            .fold(0.0f64, |acc, &x| acc.max(x))
+    }
+
+    fn get_consistency(&self) -> Option<(f64, f64)> {
+        // (consistency %, standard deviation), needing two samples to say anything
+        let history = self.get_stats_wpm_history();
+        if history.len() < 2 {
+            return None;
+        }
+        let mean = history.iter().sum::<f64>() / history.len() as f64;
+        if mean <= 0.0 {
+            return None;
+        }
+        let variance =
+            history.iter().map(|wpm| (wpm - mean).powi(2)).sum::<f64>() / history.len() as f64;
+        let std_dev = variance.sqrt();
+        let consistency = ((1.0 - std_dev / mean) * 100.0).max(0.0);
+        Some((consistency, std_dev))
+    }
+
+    fn get_accuracy(&self) -> f64 {
+        if self.total_keystrokes == 0 {
+            100.0
+        } else {
+            let correct_keystrokes = self.total_keystrokes - self.errors;
+            (correct_keystrokes as f64 / self.total_keystrokes as f64) * 100.0
+        }
+    }
+
+    fn get_saved_text(&self) -> String {
+        // Only the part of the passage that was reached, capped so long file passages
+        // don't bloat the history
+        self.target_chars
+            .iter()
+            .take(self.current_position.min(MAX_SAVED_TEXT_CHARS))
+            .collect()
+    }
+
+    fn get_result_line(&self) -> String {
+        let line = format!(
+            "{}s · {:.0} WPM · {:.0}% · {}",
+            self.get_test_length().as_secs(),
+            self.get_average_wpm(),
+            self.get_accuracy(),
+            self.text_source
+        );
+        match (self.required_key_accuracy, self.key_accuracy_passed()) {
+            (Some((key, _)), Some(passed)) => format!(
+                "{} · '{}' {}",
+                line,
+                key.escape_default(),
+                if passed { "PASSED" } else { "FAILED" }
+            ),
+            _ => line,
+        }
+    }
+
+    fn get_share_code(&self) -> Result<String, serde_json::Error> {
+        // Code file paths stay private; only the kind of source is shared
+        let text_source = if self.is_code_mode() {
+            "code".to_string()
+        } else {
+            self.text_source.to_string()
+        };
+        let keys = |keys: Vec<char>| {
+            keys.into_iter()
+                .map(|key| key.escape_default().to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        ShareCode {
+            avg_wpm: (self.get_average_wpm() * 10.0).round() / 10.0,
+            peak_wpm: (self.get_peak_wpm() * 10.0).round() / 10.0,
+            accuracy: (self.get_accuracy() * 10.0).round() / 10.0,
+            duration_seconds: self.get_test_length().as_secs(),
+            text_source,
+            slowest_keys: keys(
+                self.get_slowest_keys(SHARE_KEY_COUNT)
+                    .into_iter()
+                    .map(|(key, _)| key)
+                    .collect(),
+            ),
+            problem_keys: keys(
+                self.get_most_error_prone_keys(SHARE_KEY_COUNT)
+                    .into_iter()
+                    .map(|(key, _)| key)
+                    .collect(),
+            ),
+        }
+        .encode()
+    }
+
+    fn check_time_limit(&mut self) {
+        if self.mode == TestMode::Timed
+            && self.paused_at.is_none()
+            && let Some(start) = self.start_time
+            && start.elapsed() >= self.test_duration
+        {
+            // --finish-word holds the end back while a word is half typed
+            let overtime_left =
+                start.elapsed() < self.test_duration + Duration::from_secs(MAX_OVERTIME_SECS);
+            if self.finish_word.is_some() && self.is_mid_word() && overtime_left {
+                self.in_overtime = true;
+                return;
+            }
+            if self.in_overtime && self.finish_word == Some(Overtime::Include) {
+                // Make sure the word finished in overtime reaches the stats
+                self.last_wpm_update = None;
+                self.update_wpm();
+            }
+            self.finish();
+        }
+    }
+
+    fn is_mid_word(&self) -> bool {
+        // Part of a word typed and part still to go
+        self.current_position > 0
+            && self
+                .target_chars
+                .get(self.current_position)
+                .is_some_and(|ch| !ch.is_whitespace())
+            && !self.target_chars[self.current_position - 1].is_whitespace()
+    }
+
+    fn is_passage_complete(&self) -> bool {
+        // Zen mode only ends when you stop it
+        if self.mode == TestMode::Zen {
+            return false;
+        }
+        // Trailing whitespace that slipped past normalization doesn't have to be typed
+        self.target_chars[self.current_position.min(self.target_chars.len())..]
+            .iter()
+            .all(|ch| ch.is_whitespace())
+    }
+
+    fn end_zen(&mut self) {
+        if self.paused_at.is_some() {
+            self.toggle_pause();
+        }
+        // The stretch since the last sample still counts
+        self.last_wpm_update = None;
+        self.update_wpm();
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        self.is_finished = true;
+        self.finish_time = Some(Instant::now());
+        #[cfg(feature = "broadcast")]
+        self.publish_live_stats();
+    }
+
+    fn get_elapsed_time(&self) -> Duration {
+        // Frozen once the test is over so the summary doesn't keep counting, and while paused
+        self.start_time.map_or(Duration::ZERO, |start| {
+            match self.finish_time.or(self.paused_at) {
+                Some(end) => end.duration_since(start),
+                None => start.elapsed(),
+            }
+        })
+    }
+
+    fn toggle_pause(&mut self) {
+        // Only a running test has a clock to stop; warmup just waits for you anyway
+        if self.start_time.is_none() || self.is_finished {
+            return;
+        }
+        let now = Instant::now();
+        let Some(paused_at) = self.paused_at.take() else {
+            self.paused_at = Some(now);
+            return;
+        };
+
+        // Move the clock forward past the pause, as if it never happened
+        let paused = now.duration_since(paused_at);
+        self.paused_total += paused;
+        for instant in [
+            &mut self.start_time,
+            &mut self.last_wpm_update,
+            &mut self.last_keystroke_time,
+        ] {
+            *instant = instant.map(|time| time + paused);
+        }
+        // The key on screen is timed from now, not from before the break
+        self.start_timing_current_key();
+    }
+
+    fn get_test_length(&self) -> Duration {
+        match self.mode {
+            TestMode::Timed if self.in_overtime && self.finish_word == Some(Overtime::Include) => {
+                self.get_elapsed_time()
+            }
+            TestMode::Timed => self.test_duration,
+            TestMode::Accuracy | TestMode::Words | TestMode::Zen => self.get_elapsed_time(),
+        }
+    }
+
+    // `history` is what was on file before this run, read once per finish
+    fn save_history(&mut self, history: &[TestHistory]) -> Result<(), Box<dyn Error>> {
+        let record = TestHistory {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            duration_seconds: self.get_test_length().as_secs(),
+            avg_wpm: self.get_average_wpm(),
+            peak_wpm: self.wpm_history.iter().fold(0.0f64, |acc, &x| acc.max(x)),
+            true_peak_wpm: self.uncapped_graph.then(|| self.get_peak_wpm()),
+            chars_per_word: Some(self.chars_per_word),
+            target_text: self.save_text.then(|| self.get_saved_text()),
+            mode: Some(self.mode.to_string()),
+            wpm_data_points: Some(self.get_graph_data_points().to_vec()),
+            slowest_keys: Some(
+                self.get_slowest_keys(COMPARE_KEY_COUNT)
+                    .into_iter()
+                    .map(|(key, time)| (key, time.as_millis() as u64))
+                    .collect(),
+            ),
+            accuracy: self.get_accuracy(),
+            characters_typed: self.get_characters_typed(),
+            errors: self.errors,
+            correction_mode: self.require_correction,
+            text_source: self.text_source.to_string(),
+            max_word_length: self.max_word_length,
+            seed: Some(self.seed),
+            playlist_runs: None,
+            word_accuracy: self
+                .word_accuracy
+                .then(|| self.get_word_accuracy())
+                .flatten(),
+            burst_wpm: self.get_burst_wpm(self.burst_window),
+            reaction_ms: self
+                .reaction_time
+                .map(|reaction| reaction.as_millis() as u64),
+            case: Some(self.case.to_string()),
+            best_streak: Some(self.best_streak),
+            drill: self.get_drill(),
+            key_accuracy_passed: self.key_accuracy_passed(),
+        };
+
+        // Only runs of the same length and source are a fair comparison
+        let previous_best = history
+            .iter()
+            .filter(|past| {
+                past.playlist_runs.is_none()
+                    && past.duration_seconds == record.duration_seconds
+                    && past.text_source == record.text_source
+            })
+            .map(|past| past.avg_wpm)
+            .reduce(f64::max);
+        self.beaten_best = previous_best.filter(|&best| record.avg_wpm > best);
+
+        self.write_history_record(&record)
+    }
+
+    fn write_history_record(&mut self, record: &TestHistory) -> Result<(), Box<dyn Error>> {
+        let history_path = get_history_file_path(self.history_format, self.profile.as_deref())?;
+
+        match self.history_format {
+            HistoryFormat::Csv => {
+                // Reported once the terminal is restored, where it can be read
+                if let Some(backup) = record.append_csv(&history_path)? {
+                    self.history_backup = Some((history_path, backup));
+                }
+                Ok(())
+            }
+            HistoryFormat::Jsonl => record.append_jsonl(&history_path),
+        }
+    }
+
+    fn is_playlist_complete(&self) -> bool {
+        self.playlist_length
+            .is_some_and(|length| self.playlist_runs.len() >= length)
+    }
+
+    fn get_playlist_totals(&self) -> PlaylistRun {
+        let runs = &self.playlist_runs;
+        let duration: Duration = runs.iter().map(|run| run.duration).sum();
+        let characters_typed = runs.iter().map(|run| run.characters_typed).sum();
+        let errors = runs.iter().map(|run| run.errors).sum();
+        let total_keystrokes: usize = runs.iter().map(|run| run.total_keystrokes).sum();
+
+        // Weight each test's WPM by its length so untimed tests count fairly
+        let total_secs = duration.as_secs_f64();
+        let avg_wpm = if total_secs > 0.0 {
+            runs.iter()
+                .map(|run| run.avg_wpm * run.duration.as_secs_f64())
+                .sum::<f64>()
+                / total_secs
+        } else {
+            0.0
+        };
+        let accuracy = if total_keystrokes == 0 {
+            100.0
+        } else {
+            (total_keystrokes - errors) as f64 / total_keystrokes as f64 * 100.0
+        };
+
+        PlaylistRun {
+            duration,
+            avg_wpm,
+            peak_wpm: runs.iter().fold(0.0f64, |acc, run| acc.max(run.peak_wpm)),
+            accuracy,
+            characters_typed,
+            errors,
+            total_keystrokes,
+        }
+    }
+
+    fn record_daily_result(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(day) = self.daily else {
+            return Ok(());
+        };
+
+        // Only a missing file starts the streak over; a broken one is left alone
+        let mut results = match load_daily_results(self.profile.as_deref()) {
+            Ok(results) => results,
+            Err(e) if is_missing_file(e.as_ref()) => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        let wpm = self.get_average_wpm();
+        let result = results.entry(day).or_default();
+        result.attempts += 1;
+        if wpm > result.best_wpm || result.attempts == 1 {
+            result.best_wpm = wpm;
+            result.accuracy = self.get_accuracy();
+        }
+
+        let daily_path = get_data_file_path(DAILY_FILENAME, self.profile.as_deref())?;
+        fs::write(daily_path, serde_json::to_string(&results)?)?;
+        self.daily_summary = Some(daily_summary(&results, day, wpm));
+        Ok(())
+    }
+
+    fn record_playlist_run(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.playlist_length.is_none() {
+            return Ok(());
+        }
+
+        self.playlist_runs.push(PlaylistRun {
+            duration: self.get_test_length(),
+            avg_wpm: self.get_average_wpm(),
+            peak_wpm: self.get_peak_wpm(),
+            accuracy: self.get_accuracy(),
+            characters_typed: self.get_characters_typed(),
+            errors: self.errors,
+            total_keystrokes: self.total_keystrokes,
+        });
+        if !self.is_playlist_complete() {
+            return Ok(());
+        }
+
+        // The whole session also gets one combined record alongside the per-test ones
+        let totals = self.get_playlist_totals();
+        self.write_history_record(&TestHistory {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            duration_seconds: totals.duration.as_secs(),
+            avg_wpm: totals.avg_wpm,
+            peak_wpm: totals.peak_wpm,
+            true_peak_wpm: None,
+            chars_per_word: Some(self.chars_per_word),
+            target_text: None,
+            mode: Some(self.mode.to_string()),
+            wpm_data_points: None,
+            slowest_keys: None,
+            accuracy: totals.accuracy,
+            characters_typed: totals.characters_typed,
+            errors: totals.errors,
+            correction_mode: self.require_correction,
+            text_source: self.text_source.to_string(),
+            max_word_length: self.max_word_length,
+            seed: None,
+            playlist_runs: Some(self.playlist_runs.len()),
+            word_accuracy: None,
+            burst_wpm: None,
+            reaction_ms: None,
+            case: Some(self.case.to_string()),
+            best_streak: None,
+            drill: self.get_drill(),
+            key_accuracy_passed: None,
+        })
+    }
+
+    fn save_progress(&self) -> Result<(), Box<dyn Error>> {
+        let progress = SavedProgress {
+            target_text: self.target_text.clone(),
+            user_input: self.user_input.clone(),
+            current_position: self.current_position,
+            elapsed_ms: self.get_elapsed_time().as_millis() as u64,
+            errors: self.errors,
+            total_keystrokes: self.total_keystrokes,
+            correction_attempts: self.correction_attempts.clone(),
+            duration_seconds: self.test_duration.as_secs(),
+            correction_mode: self.require_correction,
+            text_source: self.text_source.to_string(),
+            max_word_length: self.max_word_length,
+            seed: self.seed,
+            mode: self.mode.to_string(),
+        };
+
+        let progress_path = get_data_file_path(PROGRESS_FILENAME, self.profile.as_deref())?;
+        fs::write(progress_path, serde_json::to_string(&progress)?)?;
+        Ok(())
+    }
+
+    // `history` holds the earlier runs only; this one is counted in explicitly,
+    // so it doesn't matter whether (or how precisely) it was saved
+    fn load_history_stats(&mut self, history: &[TestHistory]) {
+        let wpm = self.get_average_wpm();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        self.wpm_forecast = forecast_target_wpm(
+            history,
+            (now, wpm),
+            &self.text_source.to_string(),
+            self.target_wpm,
+        );
+        self.wpm_rank = Some(rank_wpm(history, wpm));
+    }
+
+    fn restore_progress(&mut self, progress: SavedProgress) {
+        self.target_text = progress.target_text;
+        self.target_chars = self.target_text.chars().collect();
+        self.user_input = progress.user_input;
+        self.current_position = progress.current_position.min(self.target_chars.len());
+        self.errors = progress.errors;
+        self.total_keystrokes = progress.total_keystrokes;
+        self.correction_attempts = progress.correction_attempts;
+        self.correction_attempts
+            .resize(self.target_chars.len(), false);
+        self.retry_counts = vec![0; self.target_chars.len()];
+
+        // The clock picks up where it stopped; the gap between sessions is not counted
+        let now = Instant::now();
+        let elapsed = Duration::from_millis(progress.elapsed_ms);
+        self.start_time = Some(now.checked_sub(elapsed).unwrap_or(now));
+        self.last_keystroke_time = Some(now);
+        self.start_timing_current_key();
+        // Timing before the interruption isn't saved, so the replay starts from here
+        self.typed_times = vec![elapsed; self.current_position];
+    }
+
+    fn interrupt(&mut self) {
+        // Only tests that are actually under way are worth resuming, and only if asked to
+        if !self.save_progress || self.start_time.is_none() || self.is_finished {
+            return;
+        }
+        match self.save_progress() {
+            Ok(()) => self.progress_saved = true,
+            Err(e) => eprintln!("Warning: Failed to save test progress: {}", e),
+        }
+    }
+
+    fn restart(&mut self) {
+        self.user_input.clear();
+        self.current_position = 0;
+        self.start_time = None;
+        self.wpm_history.clear();
+        self.wpm_data_points.clear();
+        self.raw_wpm_history.clear();
+        self.net_wpm_history.clear();
+        self.raw_wpm_data_points.clear();
+        self.is_finished = false;
+        self.finish_time = None;
+        self.paused_at = None;
+        self.paused_total = Duration::ZERO;
+        self.in_overtime = false;
+        self.wpm_forecast = None;
+        self.wpm_rank = None;
+        self.beaten_best = None;
+        self.daily_summary = None;
+        self.show_share_code = false;
+        self.show_char_frequency = false;
+        self.summary_tab = SummaryTab::Overview;
+        self.show_latency = false;
+        self.input_received = None;
+        self.last_key_event = None;
+        self.frame_latencies.clear();
+        self.key_intervals.clear();
+        self.typed_times.clear();
+        self.replay_start = None;
+        self.restart_deadline = None;
+        self.typo_checked_position = 0;
+        self.rejected_chars.clear();
+        self.ready_time = Some(Instant::now());
+        self.reaction_time = None;
+        // Restarting after a finished playlist begins a new one
+        if self.is_playlist_complete() {
+            self.playlist_runs.clear();
+        }
+        self.show_playlist_summary = false;
+        self.errors = 0;
+        self.space_errors = 0;
+        self.current_streak = 0;
+        self.best_streak = 0;
+        self.total_keystrokes = 0;
+        self.last_wpm_update = None;
+        self.correction_attempts.clear();
+        self.retry_counts.clear();
+        self.target_chars.clear();
+        self.key_metrics.clear();
+        self.bigram_metrics.clear();
+        self.error_kinds.clear();
+        self.last_keystroke_time = None;
+        self.current_key_start_time = None;
+        self.warmup_start = None;
+        self.stats_start_position = 0;
+        // Each run gets its own seed, drawn from the previous run's generator
+        // so a sequence started with --seed stays reproducible; daily retries
+        // get the day's passage again
+        self.seed = match self.daily {
+            Some(day) => daily_seed(day),
+            None => self.rng.r#gen(),
+        };
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.generate_text();
+        self.start_timing_current_key();
+        #[cfg(feature = "broadcast")]
+        self.publish_live_stats();
+    }
+
+    fn get_focus_end(&self) -> usize {
+        // End of the word after the current one; --focus-word highlights up to here
+        let chars = &self.target_chars;
+        let mut position = self.current_position.min(chars.len());
+        for _ in 0..2 {
+            position += chars[position..]
+                .iter()
+                .take_while(|ch| ch.is_whitespace())
+                .count();
+            position += chars[position..]
+                .iter()
+                .take_while(|ch| !ch.is_whitespace())
+                .count();
+        }
+        position
+    }
+
+    fn get_cursor_style(&self) -> Style {
+        // Red from the first rejected attempt on the current character, deepening with each one
+        let retries = self
+            .retry_counts
+            .get(self.current_position)
+            .copied()
+            .unwrap_or(0);
+        if retries == 0 {
+            self.background.highlight()
+        } else {
+            let shade = 180 - (retries.min(4) as u8) * 45;
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Rgb(255, shade, shade))
+        }
+    }
+
+    fn get_typed_word_ranges(&self) -> Vec<(usize, usize)> {
+        // Full extent of every word the cursor has reached, including the one in progress
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        while start < self.current_position {
+            if self.target_chars[start].is_whitespace() {
+                start += 1;
+                continue;
             }
+            let end = self.target_chars[start..]
+                .iter()
+                .position(|ch| ch.is_whitespace())
+                .map_or(self.target_chars.len(), |len| start + len);
+            ranges.push((start, end));
+            start = end;
         }
+        ranges
+    }
+
+    fn word_had_error(&self, (start, end): (usize, usize)) -> bool {
+        self.correction_attempts[start..end.min(self.correction_attempts.len())]
+            .iter()
+            .any(|&error| error)
+    }
+
+    fn get_missed_words(&self) -> Vec<String> {
+        // Words containing at least one position that was mistyped
+        self.get_typed_word_ranges()
+            .into_iter()
+            .filter(|&range| self.word_had_error(range))
+            .map(|(start, end)| self.target_chars[start..end].iter().collect())
+            .collect()
+    }
+
+    fn get_word_accuracy(&self) -> Option<f64> {
+        // Completed words since stats began; one wrong character spoils the whole word
+        let words: Vec<(usize, usize)> = self
+            .get_typed_word_ranges()
+            .into_iter()
+            .filter(|&(start, end)| {
+                start >= self.stats_start_position && end <= self.current_position
+            })
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+        let clean_words = words
+            .iter()
+            .filter(|&&range| !self.word_had_error(range))
+            .count();
+        Some(clean_words as f64 / words.len() as f64 * 100.0)
+    }
+
+    fn get_key_accuracy(&self, key: char) -> Option<f64> {
+        let metrics = self.key_metrics.get(&key)?;
+        let attempts = metrics.times.len();
+        if attempts == 0 {
+            return None;
+        }
+        Some(attempts.saturating_sub(metrics.errors) as f64 / attempts as f64 * 100.0)
+    }
+
+    // A key that never came up can't be judged either way
+    fn key_accuracy_passed(&self) -> Option<bool> {
+        let (key, required) = self.required_key_accuracy?;
+        self.get_key_accuracy(key)
+            .map(|accuracy| accuracy >= required)
+    }
+
+    fn get_most_retried_char(&self) -> Option<(char, usize)> {
+        self.retry_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, retries)| **retries > 0)
+            .max_by_key(|(_, retries)| **retries)
+            .map(|(pos, retries)| (self.target_chars[pos], *retries))
+    }
+
+    fn get_fastest_keys(&self, count: usize) -> Vec<(char, Duration)> {
+        let mut key_times: Vec<(char, Duration)> = self
+            .key_metrics
+            .iter()
+            .filter_map(|(key, metrics)| metrics.average_time().map(|avg_time| (*key, avg_time)))
+            .collect();
+
+        key_times.sort_by_key(|(_, time)| *time);
+        key_times.into_iter().take(count).collect()
+    }
+
+    fn get_slowest_keys(&self, count: usize) -> Vec<(char, Duration)> {
+        let mut key_times: Vec<(char, Duration)> = self
+            .key_metrics
+            .iter()
+            .filter_map(|(key, metrics)| metrics.average_time().map(|avg_time| (*key, avg_time)))
+            .collect();
+
+        key_times.sort_by_key(|(_, time)| std::cmp::Reverse(*time));
+        key_times.into_iter().take(count).collect()
     }
 
-    fn generate_word_text(&self, words: &[String]) -> String {
-        let mut rng = rand::thread_rng();
-        let mut text = String::new();
-        let required_length = self.calculate_required_text_length();
+    fn get_slowest_bigrams(&self) -> Vec<(&String, &BigramStats)> {
+        let mut bigrams: Vec<(&String, &BigramStats)> = self.bigram_metrics.iter().collect();
+        bigrams.sort_by(|(_, a), (_, b)| b.average_ms().total_cmp(&a.average_ms()));
+        bigrams
+    }
 
-        while text.len() < required_length {
-            let word = &words[rng.gen_range(0..words.len())];
-            if !text.is_empty() {
-                text.push(' ');
-            }
-            text.push_str(word);
+    fn get_key_wpm(&self, time: Duration) -> f64 {
+        // The speed you'd reach typing every key this fast, counted in the test's words
+        60.0 / (time.as_secs_f64().max(0.001) * self.chars_per_word)
+    }
+
+    fn get_hand_stats(&self) -> (HandStats, HandStats) {
+        let mut left = HandStats::default();
+        let mut right = HandStats::default();
+
+        // Keys without a hand (space, digits, punctuation) are left out
+        for (key, metrics) in &self.key_metrics {
+            let stats = match Hand::for_key(*key, self.layout) {
+                Some(Hand::Left) => &mut left,
+                Some(Hand::Right) => &mut right,
+                None => continue,
+            };
+            stats.total_time += metrics.times.iter().sum::<Duration>();
+            stats.attempts += metrics.times.len();
+            stats.errors += metrics.errors;
         }
 
-        text
+        (left, right)
     }
 
-    fn load_google10k_words(&self) -> Vec<String> {
-        GOOGLE_10000_WORDS
-            .lines()
-            .filter(|line| {
-                let word = line.trim();
-                // Filter for reasonable words: MIN_WORD_LENGTH to max_word_length characters, only letters
-                word.len() >= MIN_WORD_LENGTH
-                    && word.len() <= self.max_word_length
-                    && word.chars().all(|c| c.is_ascii_lowercase())
+    fn get_finger_stats(&self, hand: Hand) -> Vec<(Finger, HandStats)> {
+        // Per-finger totals for one hand, pinky to index, skipping unused fingers
+        Finger::ALL
+            .iter()
+            .filter_map(|&finger| {
+                let mut stats = HandStats::default();
+                for (key, metrics) in &self.key_metrics {
+                    if Finger::for_key(*key, self.layout) == Some((hand, finger)) {
+                        stats.total_time += metrics.times.iter().sum::<Duration>();
+                        stats.attempts += metrics.times.len();
+                        stats.errors += metrics.errors;
+                    }
+                }
+                (stats.attempts > 0).then_some((finger, stats))
             })
-            .map(|s| s.trim().to_string())
             .collect()
     }
 
-    fn load_system_dict_words(&self) -> Result<Vec<String>, Box<dyn Error>> {
-        let dict_content = fs::read_to_string(DICT_PATH)?;
-        let words: Vec<String> = dict_content
-            .lines()
-            .filter(|line| {
-                let word = line.trim();
-                // Filter for reasonable words: MIN_WORD_LENGTH to max_word_length characters, only letters, no proper nouns
-                word.len() >= MIN_WORD_LENGTH
-                    && word.len() <= self.max_word_length
-                    && word.chars().all(|c| c.is_ascii_lowercase())
+    fn get_learn_hint(&self) -> Option<String> {
+        // Only once the typist has been stuck on the current key for a while
+        if !self.learn || self.is_finished {
+            return None;
+        }
+        let waiting = self.current_key_start_time?.elapsed();
+        let &target = self.target_chars.get(self.current_position)?;
+        if waiting < self.hint_delay {
+            return None;
+        }
+        let shown = match target {
+            ' ' => "space".to_string(),
+            '\n' => "Enter".to_string(),
+            ch => format!("'{}'", ch),
+        };
+        let finger = match target {
+            ' ' => "either thumb".to_string(),
+            ch => match Finger::for_key(ch, self.layout) {
+                Some((hand, finger)) if ch.is_uppercase() => {
+                    format!(
+                        "your {} {} finger, holding Shift with the other hand",
+                        hand, finger
+                    )
+                }
+                Some((hand, finger)) => format!("your {} {} finger", hand, finger),
+                None => return None,
+            },
+        };
+        Some(format!("Hint: type {} with {}", shown, finger))
+    }
+
+    fn record_key_event(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_key_event {
+            self.key_intervals.push(now.duration_since(last));
+        }
+        self.last_key_event = Some(now);
+        // Keys drained in the same frame are measured from the oldest one
+        self.input_received.get_or_insert(now);
+    }
+
+    fn record_frame_drawn(&mut self) {
+        // Time from reading the key to having its effect on screen
+        if let Some(received) = self.input_received.take() {
+            self.frame_latencies.push(received.elapsed());
+        }
+    }
+
+    // (character, times in the typed part of the passage, attempts, errors), most frequent first
+    fn get_char_frequency(&self) -> Vec<(char, usize, usize, usize)> {
+        let mut counts: HashMap<char, usize> = HashMap::new();
+        // Backspacing into the warmup can leave the cursor before where stats began
+        let typed = self
+            .target_chars
+            .iter()
+            .take(self.current_position)
+            .skip(self.stats_start_position);
+        for &ch in typed {
+            *counts.entry(ch).or_insert(0) += 1;
+        }
+
+        let mut frequency: Vec<(char, usize, usize, usize)> = counts
+            .into_iter()
+            .map(|(ch, count)| {
+                let (attempts, errors) = self
+                    .key_metrics
+                    .get(&ch)
+                    .map_or((0, 0), |metrics| (metrics.times.len(), metrics.errors));
+                (ch, count, attempts, errors)
             })
-            .map(|s| s.trim().to_string())
             .collect();
+        frequency.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        frequency
+    }
 
-        Ok(words)
+    fn sorted_error_kinds(&self) -> Vec<(ErrorKind, usize)> {
+        let mut kinds: Vec<(ErrorKind, usize)> = self
+            .error_kinds
+            .iter()
+            .map(|(&kind, &count)| (kind, count))
+            .collect();
+        // Most common first; ties keep a stable order between renders
+        kinds.sort_by_key(|&(kind, count)| (std::cmp::Reverse(count), kind as usize));
+        kinds
     }
 
-    fn generate_file_text(&self, path: &PathBuf) -> String {
-        match fs::read_to_string(path) {
-            Ok(content) => {
-                let required_length = self.calculate_required_text_length();
-                self.extract_code_section(&content, required_length)
-            }
-            Err(e) => {
-                eprintln!(
-                    "Warning: Could not read file {}: {}. Using built-in texts.",
-                    path.display(),
-                    e
-                );
-                self.generate_builtin_text()
-            }
+    fn get_error_breakdown(&self) -> Option<String> {
+        // Skipped characters count as errors but aren't a kind of mistake
+        let total: usize = self.error_kinds.values().sum();
+        if total == 0 {
+            return None;
         }
+
+        let parts: Vec<String> = self
+            .sorted_error_kinds()
+            .iter()
+            .map(|(kind, count)| format!("{:.0}% {}", *count as f64 / total as f64 * 100.0, kind))
+            .collect();
+        Some(parts.join(" · "))
     }
 
-    fn extract_code_section(&self, content: &str, required_length: usize) -> String {
-        // Extract meaningful code sections (functions, methods, etc.)
-        let mut sections = Vec::new();
-        let mut current_section = String::new();
-        let mut in_function = false;
-        let mut brace_count = 0;
+    fn get_hand_balance_summary(&self) -> String {
+        let (left, right) = self.get_hand_stats();
+        let (Some(left_time), Some(right_time)) = (left.average_time(), right.average_time())
+        else {
+            return "Not enough data".to_string();
+        };
 
-        for line in content.lines() {
-            let trimmed = line.trim();
-            let line_indent = line.chars().take_while(|&c| c == ' ' || c == '\t').count();
+        let left_ms = left_time.as_secs_f64() * 1000.0;
+        let right_ms = right_time.as_secs_f64() * 1000.0;
+        let (slower, slower_ms, faster_ms) = if left_ms >= right_ms {
+            ("left", left_ms, right_ms)
+        } else {
+            ("right", right_ms, left_ms)
+        };
+        if faster_ms <= 0.0 {
+            return "Not enough data".to_string();
+        }
 
-            // Detect function/method start for various languages
-            if !in_function
-                && (trimmed.starts_with("fn ") ||         // Rust
-                trimmed.starts_with("def ") ||        // Python
-                trimmed.starts_with("function ") ||   // JavaScript
-                trimmed.starts_with("func ") ||       // Go
-                // Better OCaml function detection - must be at top level and have parameters or be recursive
-                (line_indent == 0 && trimmed.starts_with("let ") && 
-                 (trimmed.contains("(") || trimmed.starts_with("let rec "))) ||
-                trimmed.starts_with("public ") ||     // Java/C#
-                trimmed.starts_with("private ") ||    // Java/C#
-                trimmed.starts_with("protected ") ||  // Java/C#
-                trimmed.contains("fn(") ||            // Rust closures
-                trimmed.contains("=>") ||             // JS arrow functions
-                (trimmed.contains("(") && trimmed.contains(")") && trimmed.contains("{")))
-            {
-                in_function = true;
-                current_section.clear();
-            }
+        let difference = (slower_ms - faster_ms) / faster_ms * 100.0;
+        if difference < 1.0 {
+            "Balanced".to_string()
+        } else {
+            format!("{} hand {:.0}% slower", slower, difference)
+        }
+    }
 
-            if in_function {
-                current_section.push_str(line);
-                current_section.push('\n');
+    fn get_most_error_prone_keys(&self, count: usize) -> Vec<(char, usize)> {
+        let mut key_errors: Vec<(char, usize)> = self
+            .key_metrics
+            .iter()
+            .filter(|(_, metrics)| metrics.errors > 0)
+            .map(|(key, metrics)| (*key, metrics.errors))
+            .collect();
 
-                // Track braces for languages that use them
-                brace_count += line.matches('{').count() as i32;
-                brace_count -= line.matches('}').count() as i32;
+        key_errors.sort_by_key(|(_, errors)| std::cmp::Reverse(*errors));
+        key_errors.into_iter().take(count).collect()
+    }
 
-                // Detect end of function for brace-based languages
-                if brace_count == 0 && line.contains('}') {
-                    if current_section.len() >= 100 {
-                        // Only keep meaningful sections
-                        sections.push(current_section.clone());
-                    }
-                    current_section.clear();
-                    in_function = false;
-                    brace_count = 0;
+    fn get_most_accurate_keys(&self, count: usize) -> Vec<(char, f64)> {
+        let mut key_accuracy: Vec<(char, f64)> = self
+            .key_metrics
+            .iter()
+            .filter_map(|(key, metrics)| {
+                if !metrics.times.is_empty() {
+                    let total_attempts = metrics.times.len();
+                    let accuracy =
+                        (total_attempts - metrics.errors) as f64 / total_attempts as f64 * 100.0;
+                    Some((*key, accuracy))
+                } else {
+                    None
                 }
+            })
+            .collect();
 
-                // For Python and OCaml, detect based on indentation and empty lines
-                if brace_count == 0 && (
-                    // Empty line after function content
-                    (trimmed.is_empty() && current_section.trim().len() >= 50) ||
-                    // Another top-level definition (at indent 0)
-                    (!trimmed.is_empty() && line_indent == 0 && 
-                     (trimmed.starts_with("let ") || trimmed.starts_with("def ") || 
-                      trimmed.starts_with("class ") || trimmed.starts_with("type ") ||
-                      trimmed.starts_with("module ") || trimmed.starts_with("(*")))
-                ) {
-                    if current_section.len() >= 50 {
-                        sections.push(current_section.clone());
-                    }
-                    current_section.clear();
-                    in_function = false;
-                    
-                    // If we hit another function definition, start processing it
-                    if !trimmed.is_empty() && line_indent == 0 && 
-                       trimmed.starts_with("let ") && 
-                       (trimmed.contains("(") || trimmed.starts_with("let rec ")) {
-                        in_function = true;
-                        current_section.push_str(line);
-                        current_section.push('\n');
-                    }
-                }
+        key_accuracy
+            .sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        key_accuracy.into_iter().take(count).collect()
+    }
+
+    fn get_heatmap_metrics(&self, key: char) -> Option<KeyMetrics> {
+        // A key's shifted letter counts toward it, so capitals show up on the heatmaps
+        let shifted = key
+            .to_uppercase()
+            .next()
+            .filter(|&upper| upper != key)
+            .and_then(|upper| self.key_metrics.get(&upper));
+        match (self.key_metrics.get(&key), shifted) {
+            (Some(metrics), Some(shifted)) => {
+                let mut metrics = metrics.clone();
+                metrics.times.extend(&shifted.times);
+                metrics.errors += shifted.errors;
+                Some(metrics)
             }
+            (metrics, shifted) => metrics.or(shifted).cloned(),
         }
+    }
 
-        // Don't forget the last section
-        if in_function && current_section.len() >= 50 {
-            sections.push(current_section);
-        }
+    fn get_key_speed_color(&self, key: char) -> Color {
+        if let Some(metrics) = self.get_heatmap_metrics(key) {
+            if let Some(avg_time) = metrics.average_time() {
+                // Calculate all average times to determine relative performance
+                let all_times: Vec<Duration> = self
+                    .key_metrics
+                    .values()
+                    .filter_map(|m| m.average_time())
+                    .collect();
 
-        // If no functions found, fall back to using chunks of the file
-        if sections.is_empty() {
-            let lines: Vec<&str> = content.lines().collect();
-            let chunk_size = 15; // Lines per chunk
+                if all_times.len() < 2 {
+                    return Color::Gray; // Not enough data
+                }
 
-            for chunk in lines.chunks(chunk_size) {
-                let section = chunk.join("\n");
-                if section.trim().len() >= 50 {
-                    sections.push(section);
+                let min_time = all_times.iter().min().unwrap();
+                let max_time = all_times.iter().max().unwrap();
+                let time_range = max_time.as_millis() - min_time.as_millis();
+
+                if time_range == 0 {
+                    return Color::Gray; // All times are the same
+                }
+
+                // Calculate relative position (0.0 = fastest, 1.0 = slowest)
+                let relative_pos =
+                    (avg_time.as_millis() - min_time.as_millis()) as f64 / time_range as f64;
+
+                // Map to colors: green for fast, red for slow
+                let [fastest, fast, medium, slowest] = self.heatmap.speed_thresholds;
+                if relative_pos < fast {
+                    // Fast keys (green shades)
+                    if relative_pos < fastest {
+                        Color::Green // Fastest
+                    } else {
+                        Color::Rgb(144, 238, 144) // Light green
+                    }
+                } else if relative_pos < medium {
+                    // Medium keys (yellow/white)
+                    Color::Yellow
+                } else {
+                    // Slow keys (red shades)
+                    if relative_pos > slowest {
+                        Color::Red // Slowest
+                    } else {
+                        Color::Rgb(255, 99, 71) // Light red
+                    }
                 }
+            } else {
+                Color::Gray // No timing data
             }
+        } else {
+            self.background.unused_key() // Key not used
         }
+    }
 
-        if sections.is_empty() {
-            // If still no sections, just use the whole content
-            return content.chars().take(required_length).collect();
+    fn get_accuracy_color(&self, accuracy: f64) -> Color {
+        // Map accuracy to colors: green for high accuracy, red for low accuracy
+        // (default thresholds: 95%, 85%, 70%, 50%)
+        let [excellent, good, medium, poor] = self.heatmap.accuracy_thresholds;
+        if accuracy >= excellent {
+            Color::Green
+        } else if accuracy >= good {
+            Color::Rgb(144, 238, 144) // Light green
+        } else if accuracy >= medium {
+            Color::Yellow
+        } else if accuracy >= poor {
+            Color::Rgb(255, 99, 71) // Light red
+        } else {
+            Color::Red
         }
+    }
 
-        // Ensure we have enough content by combining/repeating sections as needed
-        let mut rng = rand::thread_rng();
-        let mut result = String::new();
-        let start_idx = rng.gen_range(0..sections.len());
-        let mut current_idx = start_idx;
-        let mut iterations = 0;
-        const MAX_ITERATIONS: usize = 100; // Prevent infinite loops
-        
-        while result.len() < required_length && iterations < MAX_ITERATIONS {
-            if !result.is_empty() {
-                result.push_str("\n\n"); // Add spacing between sections
-            }
-            
-            result.push_str(&sections[current_idx]);
-            
-            // Move to next section (cycle through all sections)
-            current_idx = (current_idx + 1) % sections.len();
-            iterations += 1;
-            
-            // If we've gone through all sections once and still need more content,
-            // continue cycling but add some randomization
-            if current_idx == start_idx && result.len() < required_length {
-                current_idx = rng.gen_range(0..sections.len());
-            }
-        }
-        
-        // If we somehow have too much content, truncate at a reasonable boundary
-        if result.len() > required_length * 2 {
-            // Try to truncate at a line boundary
-            let truncated = result.chars().take(required_length).collect::<String>();
-            if let Some(last_newline) = truncated.rfind('\n') {
-                truncated[..last_newline].to_string()
+    fn get_key_accuracy_color(&self, key: char) -> Color {
+        if let Some(metrics) = self.get_heatmap_metrics(key) {
+            // One lucky keystroke shouldn't paint a key green, so sparse keys stay neutral
+            if metrics.times.len() >= self.heatmap.min_key_samples {
+                let total_attempts = metrics.times.len();
+                let accuracy = (total_attempts - metrics.errors) as f64 / total_attempts as f64;
+                self.get_accuracy_color(accuracy)
             } else {
-                truncated
+                Color::Gray // Too few attempts
             }
         } else {
-            result
+            self.background.unused_key() // Key not used
         }
     }
 
-    fn handle_key_event(&mut self, key: KeyCode) {
-        if self.is_finished {
-            return;
-        }
-
-        if self.start_time.is_none() {
-            self.start_time = Some(Instant::now());
-            self.last_keystroke_time = Some(Instant::now());
-            self.start_timing_current_key();
-        }
+    fn render_speed_keyboard(&self) -> Vec<Line<'_>> {
+        self.render_keyboard(Self::get_key_speed_color)
+    }
 
-        let now = Instant::now();
+    fn render_accuracy_keyboard(&self) -> Vec<Line<'_>> {
+        self.render_keyboard(Self::get_key_accuracy_color)
+    }
 
-        match key {
-            KeyCode::Enter => {
-                // Handle Enter key for newlines in code mode
-                if self.current_position < self.target_chars.len() {
-                    let target_char = self.target_chars[self.current_position];
-                    
-                    if target_char == '\n' {
-                        // Record timing data for the newline
-                        if let Some(key_start_time) = self.current_key_start_time {
-                            let key_response_time = now.duration_since(key_start_time);
-                            self.key_metrics
-                                .entry(target_char)
-                                .or_insert_with(KeyMetrics::new)
-                                .times
-                                .push(key_response_time);
-                        }
-                        
-                        if self.require_correction {
-                            // In correction mode, treat Enter like any correct character
-                            self.user_input.push('\n');
-                            self.total_keystrokes += 1;
-                            self.current_position += 1;
-                            
-                            // Skip leading whitespace after newline in code mode
-                            self.skip_leading_whitespace();
-                            
-                            self.start_timing_current_key();
-                            self.update_wpm();
-                        } else {
-                            // In normal mode
-                            self.user_input.push('\n');
-                            self.total_keystrokes += 1;
-                            self.current_position += 1;
-                            
-                            // Skip leading whitespace after newline in code mode
-                            self.skip_leading_whitespace();
-                            
-                            self.start_timing_current_key();
-                            self.update_wpm();
-                        }
-                        
-                        self.last_keystroke_time = Some(now);
-                        
-                        if self.current_position >= self.target_chars.len() {
-                            self.is_finished = true;
-                        }
-                    } else {
-                        // Wrong key - Enter pressed when not expecting newline
-                        if self.require_correction {
-                            self.errors += 1;
-                            self.total_keystrokes += 1;
-                            if self.current_position < self.correction_attempts.len() {
-                                self.correction_attempts[self.current_position] = true;
-                            }
-                        } else {
-                            // In normal mode, treat it as an error but continue
-                            self.user_input.push('\n'); // Show what was typed
-                            self.errors += 1;
-                            self.total_keystrokes += 1;
-                            if self.current_position < self.correction_attempts.len() {
-                                self.correction_attempts[self.current_position] = true;
-                            }
-                            self.current_position += 1;
-                            self.start_timing_current_key();
-                        }
-                    }
-                }
-            }
-            KeyCode::Char(c) => {
-                if self.current_position < self.target_chars.len() {
-                    let target_char = self.target_chars[self.current_position];
+    // (indent, (label, key typed) pairs) for each heatmap row
+    fn heatmap_rows(&self) -> Vec<(&'static str, Vec<(char, char)>)> {
+        // Keyboard rows with proper spacing and indentation
+        let indents = [
+            "  ",    // top row
+            "   ",   // home row more indented
+            "     ", // bottom row most indented
+        ];
 
-                    // Record timing data only when we get the target character (correct or as an attempt)
-                    if let Some(key_start_time) = self.current_key_start_time {
-                        let key_response_time = now.duration_since(key_start_time);
-                        // Always record timing for target character attempts
-                        self.key_metrics
-                            .entry(target_char)
-                            .or_insert_with(KeyMetrics::new)
-                            .times
-                            .push(key_response_time);
-                    }
+        indents
+            .into_iter()
+            .enumerate()
+            .map(|(row_index, indent)| {
+                // Physical mode labels each key by its keycap and colors it
+                // by the character the typing layout produces there
+                let keys: Vec<(char, char)> = if self.physical_heatmap {
+                    KeyboardLayout::Qwerty.rows()[row_index]
+                        .chars()
+                        .zip(self.layout.rows()[row_index].chars())
+                        .collect()
+                } else {
+                    // The layout's own arrangement, leaving off punctuation keys like the
+                    // QWERTY heatmap always has, wherever they sit (Dvorak starts with ',.)
+                    self.layout.rows()[row_index]
+                        .chars()
+                        .filter(|ch| ch.is_alphabetic())
+                        .map(|ch| (ch, ch))
+                        .collect()
+                };
+                (indent, keys)
+            })
+            .collect()
+    }
 
-                    if self.require_correction {
-                        // In correction mode, only accept the correct character
-                        if c == target_char {
-                            self.user_input.push(c);
-                            self.total_keystrokes += 1;
-                            self.current_position += 1;
-                            self.start_timing_current_key(); // Start timing next key
-                            self.update_wpm();
-                        } else {
-                            // Wrong character - mark this position as needing correction and track error
-                            self.errors += 1;
-                            self.total_keystrokes += 1;
-                            self.key_metrics
-                                .entry(target_char)
-                                .or_insert_with(KeyMetrics::new)
-                                .errors += 1;
-                            if self.current_position < self.correction_attempts.len() {
-                                self.correction_attempts[self.current_position] = true;
-                            }
-                            // Don't start timing next key yet - stay on current key until correct
-                        }
-                    } else {
-                        // In normal mode, allow proceeding with errors
-                        self.user_input.push(c);
-                        self.total_keystrokes += 1;
+    fn render_keyboard(&self, color_for: fn(&Self, char) -> Color) -> Vec<Line<'_>> {
+        let mut lines = Vec::new();
 
-                        if c == target_char {
-                            self.current_position += 1;
-                            self.start_timing_current_key(); // Start timing next key
-                            self.update_wpm(); // Only update WPM on correct characters
-                        } else {
-                            self.errors += 1;
-                            self.key_metrics
-                                .entry(target_char)
-                                .or_insert_with(KeyMetrics::new)
-                                .errors += 1;
-                            // Mark this position as having had an error
-                            if self.current_position < self.correction_attempts.len() {
-                                self.correction_attempts[self.current_position] = true;
-                            }
-                            self.current_position += 1; // Move forward even with errors
-                            self.start_timing_current_key(); // Start timing next key
-                        }
-                    }
+        for (indent, keys) in self.heatmap_rows() {
+            let mut spans = Vec::new();
 
-                    self.last_keystroke_time = Some(now);
+            // Add indentation
+            spans.push(Span::styled(indent, Style::default()));
 
-                    if self.current_position >= self.target_chars.len() {
-                        self.is_finished = true;
-                    }
-                }
+            for (label, key) in keys {
+                let color = color_for(self, key);
+                // Create key with background color and small spacing
+                spans.push(Span::styled(
+                    format!(" {} ", label),
+                    Style::default().fg(Color::Black).bg(color),
+                ));
+                spans.push(Span::styled(" ", Style::default())); // Small space between keys
             }
-            KeyCode::Backspace => {
-                if !self.user_input.is_empty() {
-                    self.user_input.pop();
-                    self.total_keystrokes += 1;
-                    if self.current_position > 0 {
-                        self.current_position -= 1;
-                        self.start_timing_current_key(); // Start timing the key we're now on
-                    }
+
+            lines.push(Line::from(spans));
+        }
+
+        lines
+    }
+
+    fn export_heatmap_svg(&self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        // Same keys and colors as the terminal heatmaps, one keyboard above the other
+        let (page, text) = match self.background {
+            Background::Light => ("#ffffff", "#000000"),
+            _ => ("#1e1e1e", "#ffffff"),
+        };
+        let keyboards = [
+            (
+                "Speed",
+                Self::get_key_speed_color as fn(&Self, char) -> Color,
+            ),
+            ("Accuracy", Self::get_key_accuracy_color),
+        ];
+        let rows = self.heatmap_rows();
+        let keyboard_height = SVG_TITLE_HEIGHT + rows.len() as u32 * SVG_KEY_PITCH;
+        let width = 2 * SVG_MARGIN + 12 * SVG_KEY_PITCH;
+        let height = 2 * SVG_MARGIN + 2 * keyboard_height + SVG_TITLE_HEIGHT;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" font-family=\"monospace\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"{page}\"/>\n"
+        );
+        for (index, (title, color_for)) in keyboards.into_iter().enumerate() {
+            let top = SVG_MARGIN + index as u32 * keyboard_height;
+            svg.push_str(&format!(
+                "<text x=\"{SVG_MARGIN}\" y=\"{}\" fill=\"{text}\" font-size=\"18\">{title}</text>\n",
+                top + 20
+            ));
+            for (row_index, (indent, keys)) in rows.iter().enumerate() {
+                // Terminal cells are about a quarter of a key wide
+                let left = SVG_MARGIN + indent.len() as u32 * SVG_KEY_PITCH / 4;
+                let y = top + SVG_TITLE_HEIGHT + row_index as u32 * SVG_KEY_PITCH;
+                for (column, &(label, key)) in keys.iter().enumerate() {
+                    let x = left + column as u32 * SVG_KEY_PITCH;
+                    let size = SVG_KEY_PITCH - 6;
+                    svg.push_str(&format!(
+                        "<rect x=\"{x}\" y=\"{y}\" width=\"{size}\" height=\"{size}\" rx=\"4\" fill=\"{}\"/>\n\
+                         <text x=\"{}\" y=\"{}\" fill=\"#000000\" font-size=\"16\" text-anchor=\"middle\">{}</text>\n",
+                        svg_color(color_for(self, key)),
+                        x + size / 2,
+                        y + size / 2 + 6,
+                        svg_escape(label)
+                    ));
                 }
-                self.last_keystroke_time = Some(now);
             }
-            _ => {}
         }
+        svg.push_str(&format!(
+            "<text x=\"{SVG_MARGIN}\" y=\"{}\" fill=\"{text}\" font-size=\"14\">{:.0} WPM · {:.1}% accuracy · ratatype</text>\n</svg>\n",
+            height - SVG_MARGIN,
+            self.get_average_wpm(),
+            self.get_accuracy()
+        ));
+        fs::write(path, svg)?;
+        Ok(())
     }
+}
 
-    fn update_wpm(&mut self) {
-        if let Some(start) = self.start_time {
-            let now = Instant::now();
-            let elapsed_seconds = start.elapsed().as_secs_f64();
-
-            // Only update WPM if at least 1 second has passed since last update
-            // and at least 2 seconds have passed since start (to avoid huge initial values)
-            let should_update = if let Some(last_update) = self.last_wpm_update {
-                now.duration_since(last_update).as_secs_f64() >= WPM_UPDATE_INTERVAL_SECS
+// The line, plus the lowest and highest bucket averages its blocks are scaled between
+fn sparkline(values: &[f64]) -> Option<(String, f64, f64)> {
+    if values.is_empty() {
+        return None;
+    }
+    // Averaged into at most SPARKLINE_WIDTH buckets, then scaled between the lowest and highest
+    let buckets = values.len().min(SPARKLINE_WIDTH);
+    let averages: Vec<f64> = (0..buckets)
+        .map(|bucket| {
+            let start = bucket * values.len() / buckets;
+            let chunk = &values[start..(bucket + 1) * values.len() / buckets];
+            chunk.iter().sum::<f64>() / chunk.len() as f64
+        })
+        .collect();
+    let low = averages.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = averages.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = SPARKLINE_BLOCKS.len() - 1;
+    let line = averages
+        .iter()
+        .map(|&value| {
+            let level = if high > low {
+                ((value - low) / (high - low) * top as f64).round() as usize
             } else {
-                elapsed_seconds >= INITIAL_WPM_DELAY_SECS
+                top / 2
             };
+            SPARKLINE_BLOCKS[level.min(top)]
+        })
+        .collect();
+    Some((line, low, high))
+}
 
-            if should_update && elapsed_seconds >= INITIAL_WPM_DELAY_SECS {
-                let elapsed_minutes = elapsed_seconds / 60.0;
-                let words_typed = self.current_position as f64 / CHARS_PER_WORD;
-                let wpm = words_typed / elapsed_minutes;
-
-                // Cap the WPM at reasonable maximum
-                let capped_wpm = wpm.min(MAX_WPM_CAP);
+fn markdown_cell(value: &str) -> String {
+    // A bare pipe would end the table cell early
+    value.replace('|', "\\|")
+}
 
-                self.wpm_history.push(capped_wpm);
-                self.wpm_data_points.push((elapsed_seconds, capped_wpm));
-                self.last_wpm_update = Some(now);
+fn truncate_to_words(text: &str, count: usize) -> &str {
+    // Cut right after the count-th word, keeping the spacing (and code layout) before it
+    let mut words = 0;
+    let mut in_word = false;
+    for (i, ch) in text.char_indices() {
+        if !ch.is_whitespace() {
+            in_word = true;
+        } else if in_word {
+            in_word = false;
+            words += 1;
+            if words == count {
+                return &text[..i];
             }
         }
     }
+    text
+}
 
-    fn get_current_wpm(&self) -> f64 {
-        self.wpm_history.last().copied().unwrap_or(0.0)
+fn csv_char(ch: char) -> String {
+    // Quote the characters that would break the row apart
+    match ch {
+        ',' | '\n' | '\r' => format!("\"{}\"", ch),
+        KEYLOG_BACKSPACE => "\\b".to_string(),
+        KEYLOG_DELETE_WORD => "\\w".to_string(),
+        KEYLOG_SKIP => "\\s".to_string(),
+        '"' => "\"\"\"\"".to_string(),
+        _ => ch.to_string(),
     }
+}
 
-    fn get_average_wpm(&self) -> f64 {
-        if self.wpm_history.is_empty() {
-            0.0
-        } else {
-            self.wpm_history.iter().sum::<f64>() / self.wpm_history.len() as f64
-        }
+fn read_keylog(path: &PathBuf) -> Result<Vec<Keystroke>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let mut records = parse_csv_records(&text).into_iter();
+    if records.next().map(|header| header.join(",")).as_deref() != Some(KEYLOG_HEADER) {
+        return Err(format!("{} is not a --keylog file", path.display()).into());
     }
 
-    fn get_accuracy(&self) -> f64 {
-        if self.total_keystrokes == 0 {
-            100.0
-        } else {
-            let correct_keystrokes = self.total_keystrokes - self.errors;
-            (correct_keystrokes as f64 / self.total_keystrokes as f64) * 100.0
+    let keylog_char = |field: &str| match field {
+        "\\b" => Some(KEYLOG_BACKSPACE),
+        "\\w" => Some(KEYLOG_DELETE_WORD),
+        "\\s" => Some(KEYLOG_SKIP),
+        _ => {
+            let mut chars = field.chars();
+            chars.next().filter(|_| chars.next().is_none())
         }
-    }
+    };
+    records
+        .enumerate()
+        .map(|(index, fields)| {
+            let invalid = || format!("Invalid keystroke {} in {}", index + 1, path.display());
+            let [elapsed_ms, target, typed, _, response_ms] = fields.as_slice() else {
+                return Err(invalid().into());
+            };
+            Ok(Keystroke {
+                elapsed_ms: elapsed_ms.parse().map_err(|_| invalid())?,
+                target: keylog_char(target).ok_or_else(invalid)?,
+                typed: keylog_char(typed).ok_or_else(invalid)?,
+                response_ms: response_ms.parse().ok(),
+            })
+        })
+        .collect()
+}
 
-    fn get_elapsed_time(&self) -> Duration {
-        self.start_time
-            .map_or(Duration::ZERO, |start| start.elapsed())
+fn parse_csv_records(text: &str) -> Vec<Vec<String>> {
+    // Enough CSV for files we wrote ourselves: quoted fields may hold commas,
+    // doubled quotes and line breaks
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if quoted {
+            if ch != '"' {
+                field.push(ch);
+            } else if chars.next_if_eq(&'"').is_some() {
+                field.push('"');
+            } else {
+                quoted = false;
+            }
+            continue;
+        }
+        match ch {
+            '"' => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            _ => field.push(ch),
+        }
     }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
 
-    fn save_history(&self) -> Result<(), Box<dyn Error>> {
-        let history_record = TestHistory {
-            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
-            duration_seconds: self.test_duration.as_secs(),
-            avg_wpm: self.get_average_wpm(),
-            peak_wpm: self.wpm_history.iter().fold(0.0f64, |acc, &x| acc.max(x)),
-            accuracy: self.get_accuracy(),
-            characters_typed: self.current_position,
-            errors: self.errors,
-            correction_mode: self.require_correction,
-            text_source: self.text_source.to_string(),
-            max_word_length: self.max_word_length,
-        };
-
-        let history_path = self.get_history_file_path()?;
+fn svg_color(color: Color) -> String {
+    // Hex equivalents of the usual xterm palette for the named colors the heatmaps use
+    match color {
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        Color::Green => "#00cd00".to_string(),
+        Color::Yellow => "#cdcd00".to_string(),
+        Color::Red => "#cd0000".to_string(),
+        Color::Gray => "#e5e5e5".to_string(),
+        Color::DarkGray => "#7f7f7f".to_string(),
+        _ => "#ffffff".to_string(),
+    }
+}
 
-        // Check if file exists to determine if we need to write header
-        let file_exists = history_path.exists();
+fn svg_escape(ch: char) -> String {
+    match ch {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '\'' => "&apos;".to_string(),
+        _ => ch.to_string(),
+    }
+}
 
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&history_path)?;
+fn get_data_file_path(filename: &str, profile: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
+    let mut path = if let Ok(home) = env::var("HOME") {
+        PathBuf::from(home)
+    } else {
+        env::current_dir()?
+    };
 
-        // Write CSV header if file is new
-        if !file_exists {
-            writeln!(
-                file,
-                "timestamp,duration_seconds,avg_wpm,peak_wpm,accuracy,characters_typed,errors,correction_mode,text_source,max_word_length"
-            )?;
+    // Profiles get their own files; the default profile keeps the original names
+    match profile {
+        Some(name) => {
+            path.push(filename.replacen(".ratatype_", &format!(".ratatype_{}_", name), 1))
         }
+        None => path.push(filename),
+    }
+    Ok(path)
+}
 
-        // Write the record
-        writeln!(
-            file,
-            "{},{},{:.2},{:.2},{:.2},{},{},{},{},{}",
-            history_record.timestamp,
-            history_record.duration_seconds,
-            history_record.avg_wpm,
-            history_record.peak_wpm,
-            history_record.accuracy,
-            history_record.characters_typed,
-            history_record.errors,
-            history_record.correction_mode,
-            history_record.text_source,
-            history_record.max_word_length
-        )?;
+// What prepare_csv_history found in an existing CSV history
+enum CsvHistoryState {
+    Appendable,
+    // Needs a header before the first row
+    Empty,
+    // Didn't start with a known header, so it was moved to this backup
+    MovedAside(PathBuf),
+}
 
-        Ok(())
+// Makes an existing CSV history safe to append to
+fn prepare_csv_history(path: &PathBuf) -> Result<CsvHistoryState, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    if content.is_empty() {
+        return Ok(CsvHistoryState::Empty);
     }
 
-    fn get_history_file_path(&self) -> Result<PathBuf, Box<dyn Error>> {
-        let mut path = if let Ok(home) = env::var("HOME") {
-            PathBuf::from(home)
-        } else {
-            env::current_dir()?
-        };
-
-        path.push(HISTORY_FILENAME);
-        Ok(path)
+    // Columns are only ever appended, so older files carry a prefix of the current header
+    let header = content.lines().next().unwrap_or_default();
+    if header != CSV_HEADER && !CSV_HEADER.starts_with(&format!("{},", header)) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let backup = PathBuf::from(format!("{}.{}.bak", path.display(), timestamp));
+        fs::rename(path, &backup)?;
+        return Ok(CsvHistoryState::MovedAside(backup));
     }
 
-    fn restart(&mut self) {
-        self.user_input.clear();
-        self.current_position = 0;
-        self.start_time = None;
-        self.wpm_history.clear();
-        self.wpm_data_points.clear();
-        self.is_finished = false;
-        self.errors = 0;
-        self.total_keystrokes = 0;
-        self.last_wpm_update = None;
-        self.correction_attempts.clear();
-        self.target_chars.clear();
-        self.key_metrics.clear();
-        self.last_keystroke_time = None;
-        self.current_key_start_time = None;
-        self.generate_text();
-        self.start_timing_current_key();
+    // A write cut short leaves a partial last row; start the next one on its own line
+    if !content.ends_with('\n') {
+        let mut file = OpenOptions::new().append(true).open(path)?;
+        writeln!(file)?;
     }
+    Ok(CsvHistoryState::Appendable)
+}
 
-    fn get_fastest_keys(&self, count: usize) -> Vec<(char, Duration)> {
-        let mut key_times: Vec<(char, Duration)> = self
-            .key_metrics
-            .iter()
-            .filter_map(|(key, metrics)| metrics.average_time().map(|avg_time| (*key, avg_time)))
-            .collect();
+fn get_history_file_path(
+    format: HistoryFormat,
+    profile: Option<&str>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let filename = match format {
+        HistoryFormat::Csv => HISTORY_FILENAME,
+        HistoryFormat::Jsonl => HISTORY_JSONL_FILENAME,
+    };
+    get_data_file_path(filename, profile)
+}
 
-        key_times.sort_by_key(|(_, time)| *time);
-        key_times.into_iter().take(count).collect()
-    }
+fn load_progress(profile: Option<&str>) -> Result<SavedProgress, Box<dyn Error>> {
+    let progress_path = get_data_file_path(PROGRESS_FILENAME, profile)?;
+    let content = fs::read_to_string(&progress_path)
+        .map_err(|e| format!("No interrupted test to resume ({})", e))?;
+    Ok(serde_json::from_str(&content)?)
+}
 
-    fn get_slowest_keys(&self, count: usize) -> Vec<(char, Duration)> {
-        let mut key_times: Vec<(char, Duration)> = self
-            .key_metrics
-            .iter()
-            .filter_map(|(key, metrics)| metrics.average_time().map(|avg_time| (*key, avg_time)))
-            .collect();
+fn load_bigram_stats(
+    profile: Option<&str>,
+) -> Result<HashMap<String, BigramStats>, Box<dyn Error>> {
+    let bigrams_path = get_data_file_path(BIGRAMS_FILENAME, profile)?;
+    Ok(serde_json::from_str(&fs::read_to_string(bigrams_path)?)?)
+}
 
-        key_times.sort_by_key(|(_, time)| std::cmp::Reverse(*time));
-        key_times.into_iter().take(count).collect()
-    }
+fn is_missing_file(error: &(dyn Error + 'static)) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+}
 
-    fn get_most_error_prone_keys(&self, count: usize) -> Vec<(char, usize)> {
-        let mut key_errors: Vec<(char, usize)> = self
-            .key_metrics
-            .iter()
-            .filter(|(_, metrics)| metrics.errors > 0)
-            .map(|(key, metrics)| (*key, metrics.errors))
-            .collect();
+fn load_word_reviews(profile: Option<&str>) -> Result<HashMap<String, WordReview>, Box<dyn Error>> {
+    let reviews_path = get_data_file_path(WORD_REVIEWS_FILENAME, profile)?;
+    Ok(serde_json::from_str(&fs::read_to_string(reviews_path)?)?)
+}
 
-        key_errors.sort_by_key(|(_, errors)| std::cmp::Reverse(*errors));
-        key_errors.into_iter().take(count).collect()
+fn due_words(reviews: &HashMap<String, WordReview>, now: u64) -> Vec<String> {
+    // Sorted so the same seed and review state give the same passage
+    let mut words: Vec<String> = reviews
+        .iter()
+        .filter(|(_, review)| review.due <= now)
+        .map(|(word, _)| word.clone())
+        .collect();
+    words.sort();
+    words
+}
+
+fn get_due_words(profile: Option<&str>) -> Vec<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    due_words(&load_word_reviews(profile).unwrap_or_default(), now)
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / SECS_PER_DAY as u64)
+}
+
+fn daily_seed(day: u64) -> u64 {
+    // Spread consecutive days apart so neighbouring seeds don't look related
+    day.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+fn format_day(day: u64) -> String {
+    // Days since the epoch to a UTC calendar date (Howard Hinnant's civil_from_days)
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+fn load_daily_results(profile: Option<&str>) -> Result<BTreeMap<u64, DailyResult>, Box<dyn Error>> {
+    let daily_path = get_data_file_path(DAILY_FILENAME, profile)?;
+    Ok(serde_json::from_str(&fs::read_to_string(daily_path)?)?)
+}
+
+fn daily_summary(results: &BTreeMap<u64, DailyResult>, day: u64, wpm: f64) -> String {
+    // Consecutive days with a result, counting back from today
+    let streak = (0..=day)
+        .rev()
+        .take_while(|past_day| results.contains_key(past_day))
+        .count();
+    let best_today = results.get(&day).map_or(wpm, |result| result.best_wpm);
+    let previous = results
+        .range(..day)
+        .next_back()
+        .map(|(&past_day, result)| {
+            format!(
+                ", last daily {:.0} WPM on {}",
+                result.best_wpm,
+                format_day(past_day)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "{} · streak {} day{} · best today {:.0} WPM{}",
+        format_day(day),
+        streak,
+        if streak == 1 { "" } else { "s" },
+        best_today,
+        previous
+    )
+}
+
+fn get_slow_bigrams(profile: Option<&str>) -> Vec<String> {
+    // Pairs seen too rarely are left out so one fumble doesn't define the drill
+    let mut bigrams: Vec<(String, BigramStats)> = load_bigram_stats(profile)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, stats)| stats.count >= MIN_BIGRAM_SAMPLES)
+        .collect();
+    bigrams.sort_by(|(_, a), (_, b)| b.average_ms().total_cmp(&a.average_ms()));
+    bigrams
+        .into_iter()
+        .take(DRILL_BIGRAM_COUNT)
+        .map(|(bigram, _)| bigram)
+        .collect()
+}
+
+fn load_menu_settings(profile: Option<&str>) -> Result<MenuSettings, Box<dyn Error>> {
+    let settings_path = get_data_file_path(SETTINGS_FILENAME, profile)?;
+    Ok(serde_json::from_str(&fs::read_to_string(settings_path)?)?)
+}
+
+fn save_menu_settings(
+    settings: &MenuSettings,
+    profile: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let settings_path = get_data_file_path(SETTINGS_FILENAME, profile)?;
+    fs::write(settings_path, serde_json::to_string(settings)?)?;
+    Ok(())
+}
+
+fn clear_progress(profile: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let progress_path = get_data_file_path(PROGRESS_FILENAME, profile)?;
+    if progress_path.exists() {
+        fs::remove_file(progress_path)?;
     }
+    Ok(())
+}
 
-    fn get_most_accurate_keys(&self, count: usize) -> Vec<(char, f64)> {
-        let mut key_accuracy: Vec<(char, f64)> = self
-            .key_metrics
-            .iter()
-            .filter_map(|(key, metrics)| {
-                if !metrics.times.is_empty() {
-                    let total_attempts = metrics.times.len();
-                    let accuracy =
-                        (total_attempts - metrics.errors) as f64 / total_attempts as f64 * 100.0;
-                    Some((*key, accuracy))
-                } else {
-                    None
-                }
-            })
-            .collect();
+fn load_history(
+    format: HistoryFormat,
+    profile: Option<&str>,
+) -> Result<Vec<TestHistory>, Box<dyn Error>> {
+    let history_path = get_history_file_path(format, profile)?;
+    if !history_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&history_path)?;
+    // Malformed lines (including the CSV header) are skipped rather than failing the whole load
+    let records = match format {
+        HistoryFormat::Csv => content
+            .lines()
+            .filter_map(TestHistory::from_csv_line)
+            .collect(),
+        HistoryFormat::Jsonl => content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+    };
+
+    Ok(records)
+}
+
+// `latest` is the (timestamp, WPM) of the run just finished, which `history` doesn't hold
+fn forecast_target_wpm(
+    history: &[TestHistory],
+    latest: (u64, f64),
+    text_source: &str,
+    target_wpm: f64,
+) -> Option<String> {
+    // Least-squares fit of average WPM against time, over runs with the same source
+    let runs: Vec<(f64, f64)> = history
+        .iter()
+        .filter(|record| record.text_source == text_source && record.playlist_runs.is_none())
+        .map(|record| (record.timestamp, record.avg_wpm))
+        .chain([latest])
+        .map(|(timestamp, wpm)| (timestamp as f64 / SECS_PER_DAY, wpm))
+        .collect();
+    if runs.len() < MIN_FORECAST_RUNS {
+        return None;
+    }
 
-        key_accuracy
-            .sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
-        key_accuracy.into_iter().take(count).collect()
+    let count = runs.len() as f64;
+    let mean_day = runs.iter().map(|(day, _)| day).sum::<f64>() / count;
+    let mean_wpm = runs.iter().map(|(_, wpm)| wpm).sum::<f64>() / count;
+    let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+    for (day, wpm) in &runs {
+        sxx += (day - mean_day).powi(2);
+        sxy += (day - mean_day) * (wpm - mean_wpm);
+        syy += (wpm - mean_wpm).powi(2);
     }
 
-    fn get_key_speed_color(&self, key: char) -> Color {
-        if let Some(metrics) = self.key_metrics.get(&key) {
-            if let Some(avg_time) = metrics.average_time() {
-                // Calculate all average times to determine relative performance
-                let all_times: Vec<Duration> = self
-                    .key_metrics
-                    .values()
-                    .filter_map(|m| m.average_time())
-                    .collect();
+    // Runs spanning less than a day say nothing about a trend over days
+    let first_day = runs
+        .iter()
+        .map(|(day, _)| *day)
+        .fold(f64::INFINITY, f64::min);
+    let last_day = runs.iter().map(|(day, _)| *day).fold(0.0, f64::max);
+    if last_day - first_day < 1.0 || syy == 0.0 {
+        return None;
+    }
 
-                if all_times.len() < 2 {
-                    return Color::Gray; // Not enough data
-                }
+    // Flat, declining or noisy trends don't support a projection
+    let slope = sxy / sxx;
+    let r_squared = sxy * sxy / (sxx * syy);
+    if slope <= 0.0 || r_squared < MIN_FORECAST_R_SQUARED {
+        return None;
+    }
 
-                let min_time = all_times.iter().min().unwrap();
-                let max_time = all_times.iter().max().unwrap();
-                let time_range = max_time.as_millis() - min_time.as_millis();
+    let current_wpm = mean_wpm + slope * (last_day - mean_day);
+    if current_wpm >= target_wpm {
+        return Some(format!("{:.0} WPM reached", target_wpm));
+    }
+    let days = (target_wpm - current_wpm) / slope;
+    if days > MAX_FORECAST_DAYS {
+        return None;
+    }
 
-                if time_range == 0 {
-                    return Color::Gray; // All times are the same
-                }
+    let eta = if days < 1.0 {
+        "under a day".to_string()
+    } else if days < 14.0 {
+        format!("~{:.0} days", days)
+    } else if days < 60.0 {
+        format!("~{:.0} weeks", days / 7.0)
+    } else {
+        format!("~{:.0} months", days / 30.0)
+    };
+    Some(format!("at this rate, {:.0} WPM in {}", target_wpm, eta))
+}
 
-                // Calculate relative position (0.0 = fastest, 1.0 = slowest)
-                let relative_pos =
-                    (avg_time.as_millis() - min_time.as_millis()) as f64 / time_range as f64;
+// Ranks a run against `history`, which holds the earlier runs but not this one
+fn rank_wpm(history: &[TestHistory], wpm: f64) -> String {
+    // Playlist summaries repeat their runs, so only individual tests are ranked
+    let runs: Vec<f64> = history
+        .iter()
+        .filter(|record| record.playlist_runs.is_none())
+        .map(|record| record.avg_wpm)
+        .collect();
+    if runs.is_empty() {
+        return "No history to compare".to_string();
+    }
 
-                // Map to colors: green for fast, red for slow
-                if relative_pos < 0.33 {
-                    // Fast keys (green shades)
-                    if relative_pos < 0.16 {
-                        Color::Green // Fastest
-                    } else {
-                        Color::Rgb(144, 238, 144) // Light green
-                    }
-                } else if relative_pos < 0.67 {
-                    // Medium keys (yellow/white)
-                    Color::Yellow
-                } else {
-                    // Slow keys (red shades)
-                    if relative_pos > 0.83 {
-                        Color::Red // Slowest
-                    } else {
-                        Color::Rgb(255, 99, 71) // Light red
-                    }
-                }
-            } else {
-                Color::Gray // No timing data
-            }
-        } else {
-            Color::DarkGray // Key not used
+    // This run counts as one of the runs, and as one at or above its own speed
+    let total = runs.len() + 1;
+    let at_or_above = runs.iter().filter(|&&run| run >= wpm).count() + 1;
+    if at_or_above == 1 {
+        return format!("Best of your {} runs", total);
+    }
+    let percent = (at_or_above as f64 / total as f64 * 100.0).ceil();
+    format!("Top {:.0}% of your {} runs", percent, total)
+}
+
+#[cfg(feature = "broadcast")]
+#[derive(Debug, Clone, Default, Serialize)]
+struct LiveStats {
+    wpm: f64,
+    accuracy: f64,
+    errors: usize,
+    elapsed_seconds: f64,
+    finished: bool,
+}
+
+#[cfg(feature = "broadcast")]
+fn start_broadcast(
+    addr: &str,
+) -> Result<std::sync::Arc<std::sync::Mutex<LiveStats>>, Box<dyn Error>> {
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    let listener = std::net::TcpListener::bind(addr)?;
+    let live_stats = Arc::new(Mutex::new(LiveStats::default()));
+    let shared = Arc::clone(&live_stats);
+    // Every request gets the latest snapshot, whatever its path; overlays just poll
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+            let mut request = [0u8; MAX_BROADCAST_REQUEST_BYTES];
+            let _ = stream.read(&mut request);
+            let body = match shared.lock() {
+                Ok(stats) => serde_json::to_string(&*stats).unwrap_or_default(),
+                Err(_) => break,
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+    Ok(live_stats)
+}
+
+#[cfg(feature = "url")]
+fn fetch_url_text(url: &str) -> Result<PathBuf, Box<dyn Error>> {
+    // Downloads are the same for everyone, so the cache is shared between profiles
+    let mut cache_path = get_data_file_path(URL_CACHE_DIRNAME, None)?;
+    cache_path.push(format!("{:016x}.txt", url_cache_key(url)));
+
+    // Only hit the network the first time a URL is used
+    if !cache_path.exists() {
+        let body = ureq::get(url).call()?.into_string()?;
+        let text = normalize_fetched_text(&body);
+        if text.trim().is_empty() {
+            return Err("fetched page contains no text".into());
+        }
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent)?;
         }
+        fs::write(&cache_path, text)?;
     }
 
-    fn get_key_accuracy_color(&self, key: char) -> Color {
-        if let Some(metrics) = self.key_metrics.get(&key) {
-            if !metrics.times.is_empty() {
-                let total_attempts = metrics.times.len();
-                let accuracy = (total_attempts - metrics.errors) as f64 / total_attempts as f64;
+    Ok(cache_path)
+}
 
-                // Map accuracy to colors: green for high accuracy, red for low accuracy
-                if accuracy >= 0.95 {
-                    Color::Green // 95%+ accuracy
-                } else if accuracy >= 0.85 {
-                    Color::Rgb(144, 238, 144) // Light green (85-94%)
-                } else if accuracy >= 0.70 {
-                    Color::Yellow // Medium accuracy (70-84%)
-                } else if accuracy >= 0.50 {
-                    Color::Rgb(255, 99, 71) // Light red (50-69%)
-                } else {
-                    Color::Red // Low accuracy (<50%)
-                }
-            } else {
-                Color::Gray // No data
+#[cfg(feature = "url")]
+fn url_cache_key(url: &str) -> u64 {
+    // 64-bit FNV-1a. Unlike std's DefaultHasher it never changes between Rust
+    // releases, so a rebuilt binary still finds the pages it cached before
+    url.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(feature = "url")]
+fn normalize_fetched_text(body: &str) -> String {
+    // Unify line endings, expand tabs and drop trailing whitespace and runs of blank lines
+    let mut text = String::new();
+    let mut previous_blank = true;
+    for line in body.replace("\r\n", "\n").replace('\r', "\n").lines() {
+        let line = line.replace('\t', "    ");
+        let line = line.trim_end();
+        if line.is_empty() {
+            if !previous_blank {
+                text.push('\n');
             }
+            previous_blank = true;
         } else {
-            Color::DarkGray // Key not used
+            text.push_str(line);
+            text.push('\n');
+            previous_blank = false;
         }
     }
+    text.trim_end().to_string()
+}
 
-    fn render_speed_keyboard(&self) -> Vec<Line> {
-        // QWERTY layout with proper spacing and indentation
-        let keyboard_rows = vec![
-            ("qwertyuiop", "  "), // (keys, indent)
-            ("asdfghjkl", "   "), // home row more indented
-            ("zxcvbnm", "     "), // bottom row most indented
-        ];
-
-        let mut lines = Vec::new();
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // Piped text only stands in for the default source, never one asked for by name
+    let text_source_given = [
+        "text_source",
+        "file",
+        #[cfg(feature = "url")]
+        "url",
+    ]
+    .iter()
+    .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine));
+
+    // Fetch before entering raw mode so warnings are visible
+    #[cfg(feature = "url")]
+    if let Some(url) = &args.url {
+        args.text_source = match fetch_url_text(url) {
+            Ok(path) => TextSource::File(path),
+            Err(e) => {
+                eprintln!(
+                    "Warning: Could not fetch {}: {}. Using built-in texts.",
+                    url, e
+                );
+                TextSource::Builtin
+            }
+        };
+    }
 
-        for (row, indent) in keyboard_rows {
-            let mut spans = Vec::new();
+    if let Some(path) = &args.file {
+        args.text_source = TextSource::Passage(path.clone());
+    }
 
-            // Add indentation
-            spans.push(Span::styled(indent, Style::default()));
+    if args.replay_settings {
+        let seed = args.seed;
+        let history = load_history(args.history_format, args.profile.as_deref())?;
+        let record = history
+            .iter()
+            .rev()
+            .find(|record| record.seed == seed)
+            .ok_or_else(|| {
+                format!(
+                    "No test with seed {} found in history",
+                    seed.unwrap_or_default()
+                )
+            })?;
+        args.apply_history_settings(record)?;
+    }
 
-            for ch in row.chars() {
-                let color = self.get_key_speed_color(ch);
-                // Create key with background color and small spacing
-                spans.push(Span::styled(
-                    format!(" {} ", ch),
-                    Style::default().fg(Color::Black).bg(color),
-                ));
-                spans.push(Span::styled(" ", Style::default())); // Small space between keys
-            }
+    args.key_accuracy_requirement()?;
 
-            lines.push(Line::from(spans));
+    // Headless: nothing is drawn or saved, only the stats are printed
+    if let Some(path) = &args.bench {
+        let mut app = App::new(&args);
+        if app.key_filter_ignored {
+            eprintln!(
+                "Warning: Fewer than {} words match --only-keys/--avoid-keys. Ignoring them.",
+                MIN_KEY_FILTER_WORDS
+            );
         }
-
-        lines
+        if let Some(reason) = app.passage_fallback {
+            eprintln!(
+                "Warning: --file passage not used ({}). Using built-in texts.",
+                reason
+            );
+        }
+        app.replay_keylog(&read_keylog(path)?)?;
+        println!("{}", app.get_bench_report());
+        return Ok(());
     }
 
-    fn render_accuracy_keyboard(&self) -> Vec<Line> {
-        // QWERTY layout with proper spacing and indentation
-        let keyboard_rows = vec![
-            ("qwertyuiop", "  "), // (keys, indent)
-            ("asdfghjkl", "   "), // home row more indented
-            ("zxcvbnm", "     "), // bottom row most indented
-        ];
+    // Bind before raw mode so a busy address is reported where it can be read
+    #[cfg(feature = "broadcast")]
+    let live_stats = args
+        .broadcast
+        .as_deref()
+        .and_then(|addr| match start_broadcast(addr) {
+            Ok(live_stats) => Some(live_stats),
+            Err(e) => {
+                eprintln!(
+                    "Warning: Could not serve live stats on {}: {}. Continuing without.",
+                    addr, e
+                );
+                None
+            }
+        });
 
-        let mut lines = Vec::new();
+    if let Some(code) = &args.decode_share {
+        println!("{}", ShareCode::decode(code)?);
+        return Ok(());
+    }
 
-        for (row, indent) in keyboard_rows {
-            let mut spans = Vec::new();
+    // Look both runs up before entering raw mode so a bad id is reported plainly
+    let comparison = match &args.compare {
+        Some(ids) => {
+            let history = load_history(args.history_format, args.profile.as_deref())?;
+            let find_run = |id: u64| {
+                history
+                    .iter()
+                    .find(|record| record.timestamp == id)
+                    .cloned()
+                    .ok_or_else(|| format!("No test with id {} found in history", id))
+            };
+            Some((find_run(ids[0])?, find_run(ids[1])?))
+        }
+        None => None,
+    };
 
-            // Add indentation
-            spans.push(Span::styled(indent, Style::default()));
+    // Newest first; combined playlist records would repeat runs already listed
+    let past_runs = if args.show_history {
+        let history = load_history(args.history_format, args.profile.as_deref())?;
+        Some(
+            history
+                .into_iter()
+                .rev()
+                .filter(|record| record.playlist_runs.is_none())
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        None
+    };
 
-            for ch in row.chars() {
-                let color = self.get_key_accuracy_color(ch);
-                // Create key with background color and small spacing
-                spans.push(Span::styled(
-                    format!(" {} ", ch),
-                    Style::default().fg(Color::Black).bg(color),
-                ));
-                spans.push(Span::styled(" ", Style::default())); // Small space between keys
+    // Piped text has to be read before raw mode takes over the terminal
+    let mut piped = false;
+    if !io::stdin().is_terminal()
+        && !text_source_given
+        && !args.resume
+        && !args.replay_settings
+        && !args.daily
+        && comparison.is_none()
+        && past_runs.is_none()
+    {
+        match read_stdin_text() {
+            Ok(Some(path)) => {
+                args.text_source = TextSource::Passage(path);
+                piped = true;
             }
-
-            lines.push(Line::from(spans));
+            Ok(None) => {}
+            Err(e) => eprintln!(
+                "Warning: Could not read piped text: {}. Using normal text.",
+                e
+            ),
         }
+    }
 
-        lines
+    // A missing or stale progress file is reported before raw mode, like the lookups above
+    let progress = if args.resume {
+        let progress = load_progress(args.profile.as_deref())?;
+        args.apply_progress_settings(&progress)?;
+        Some(progress)
+    } else {
+        None
+    };
+
+    if args.drill_bigrams && get_slow_bigrams(args.profile.as_deref()).is_empty() {
+        eprintln!(
+            "Warning: Not enough bigram timings recorded yet for --drill-bigrams. Using normal text."
+        );
+    }
+    if args.review && get_due_words(args.profile.as_deref()).is_empty() {
+        eprintln!("Warning: No missed words are due for review. Using normal text.");
+    }
+    if args.save_text && args.history_format == HistoryFormat::Csv {
+        eprintln!("Warning: --save-text only applies with --history-format jsonl.");
     }
-}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+    // Running with no arguments at all opens the menu too, unless text was piped in
+    let show_menu = args.menu || (env::args().len() == 1 && !piped);
+    if show_menu && let Ok(settings) = load_menu_settings(args.profile.as_deref()) {
+        // Saved choices only seed the menu, so a stale file is harmless
+        if let Err(e) = args.apply_menu_settings(&settings) {
+            eprintln!("Warning: Ignoring saved menu settings: {}", e);
+        }
+    }
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -1046,27 +5500,179 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app = App::new(
-        args.duration,
-        args.require_correction,
-        args.text_source,
-        args.max_word_length,
-    );
+    if let Some((first, second)) = &comparison {
+        let res = run_compare(&mut terminal, first, second, args.background.resolve());
+        restore_terminal(&mut terminal)?;
+        if let Err(err) = res {
+            println!("{err:?}");
+        }
+        return Ok(());
+    }
+
+    if let Some(runs) = &past_runs {
+        let res = run_history_view(&mut terminal, runs, args.background.resolve());
+        restore_terminal(&mut terminal)?;
+        if let Err(err) = res {
+            println!("{err:?}");
+        }
+        return Ok(());
+    }
+
+    if show_menu {
+        let mut menu = SettingsMenu::new(&args);
+        let res = run_menu(&mut terminal, &mut menu);
+        if !matches!(res, Ok(true)) {
+            restore_terminal(&mut terminal)?;
+            if let Err(err) = res {
+                println!("{err:?}");
+            }
+            return Ok(());
+        }
+        // A bad choice is reported on the normal screen, like a failed menu
+        let settings = match menu
+            .get_settings()
+            .and_then(|settings| args.apply_menu_settings(&settings).map(|()| settings))
+        {
+            Ok(settings) => settings,
+            Err(err) => {
+                restore_terminal(&mut terminal)?;
+                println!("{err:?}");
+                return Ok(());
+            }
+        };
+        if let Err(e) = save_menu_settings(&settings, args.profile.as_deref()) {
+            eprintln!("Warning: Failed to save menu settings: {}", e);
+        }
+    }
+
+    let mut app = App::new(&args);
+    #[cfg(feature = "broadcast")]
+    {
+        app.live_stats = live_stats;
+    }
+    if let Some(progress) = progress {
+        app.restore_progress(progress);
+    }
     let res = run_app(&mut terminal, &mut app);
 
+    restore_terminal(&mut terminal)?;
+
+    if let Some((path, backup)) = &app.history_backup {
+        eprintln!(
+            "Warning: {} doesn't start with the expected header. Moved it to {} and started a new history.",
+            path.display(),
+            backup.display()
+        );
+    }
+    for warning in &app.deferred_warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if let Err(err) = res {
+        println!("{err:?}");
+    } else if app.progress_saved {
+        println!("Test progress saved. Run with --resume to continue where you left off.");
+    } else if args.print_result
+        && let Some(line) = &app.last_result_line
+    {
+        // Leaves a record of the last completed test in the terminal scrollback
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn read_stdin_text() -> Result<Option<PathBuf>, Box<dyn Error>> {
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text)?;
+    // Nothing piped in (e.g. < /dev/null) means a normal test
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+
+    // Saved as a --file passage so restarts and the history can point at it
+    let path = get_data_file_path(STDIN_FILENAME, None)?;
+    fs::write(&path, text)?;
+    Ok(Some(path))
+}
+
+fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
         DisableMouseCapture
     )?;
-    terminal.show_cursor()?;
+    terminal.show_cursor()
+}
 
-    if let Err(err) = res {
-        println!("{err:?}");
+// Returns false if the menu was closed without starting a test
+fn run_menu<B: Backend>(terminal: &mut Terminal<B>, menu: &mut SettingsMenu) -> io::Result<bool> {
+    loop {
+        terminal.draw(|f| render_menu_screen(f, menu))?;
+
+        if event::poll(Duration::from_millis(RENDER_INTERVAL_MS))?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Esc => return Ok(false),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(false);
+                }
+                KeyCode::Enter => return Ok(true),
+                code => menu.handle_key_event(code),
+            }
+        }
     }
+}
 
-    Ok(())
+fn run_compare<B: Backend>(
+    terminal: &mut Terminal<B>,
+    first: &TestHistory,
+    second: &TestHistory,
+    background: Background,
+) -> io::Result<()> {
+    loop {
+        terminal.draw(|f| render_compare_screen(f, first, second, background))?;
+
+        if event::poll(Duration::from_millis(RENDER_INTERVAL_MS))?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            return Ok(());
+        }
+    }
+}
+
+fn run_history_view<B: Backend>(
+    terminal: &mut Terminal<B>,
+    runs: &[TestHistory],
+    background: Background,
+) -> io::Result<()> {
+    let last = runs.len().saturating_sub(1);
+    let mut offset = 0;
+    loop {
+        terminal.draw(|f| render_history_screen(f, runs, offset, background))?;
+
+        if event::poll(Duration::from_millis(RENDER_INTERVAL_MS))?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => offset = offset.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => offset = (offset + 1).min(last),
+                KeyCode::PageUp => offset = offset.saturating_sub(HISTORY_PAGE_ROWS),
+                KeyCode::PageDown => offset = (offset + HISTORY_PAGE_ROWS).min(last),
+                KeyCode::Home => offset = 0,
+                KeyCode::End => offset = last,
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
 }
 
 fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
@@ -1074,31 +5680,144 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
         // Main typing test loop
         loop {
             terminal.draw(|f| ui(f, app))?;
+            if app.debug {
+                app.record_frame_drawn();
+            }
 
-            if event::poll(Duration::from_millis(POLL_INTERVAL_MS))? {
-                if let Event::Key(key) = event::read()? {
-                    if key.kind == KeyEventKind::Press {
-                        match key.code {
-                            KeyCode::Esc => return Ok(()),
-                            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
-                            _ => app.handle_key_event(key.code),
-                        }
+            // Wait for the first key, then drain everything already queued before
+            // redrawing so fast bursts aren't spread over several frames. Keys left
+            // after the test ends stay queued for the results screen as before
+            let mut timeout = Duration::from_millis(POLL_INTERVAL_MS);
+            while !app.is_finished && event::poll(timeout)? {
+                timeout = Duration::ZERO;
+                // A long burst mustn't run past the end of the test
+                app.check_time_limit();
+                if app.is_finished {
+                    break;
+                }
+                let key = match event::read()? {
+                    Event::Key(key) => key,
+                    // Redraw at the new size right away instead of after the queued keys
+                    Event::Resize(_, _) => break,
+                    _ => continue,
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if app.debug {
+                    app.record_key_event();
+                }
+                match key.code {
+                    // Esc is how a zen session ends, so it goes to the summary instead of quitting
+                    KeyCode::Esc if app.mode == TestMode::Zen && app.start_time.is_some() => {
+                        app.end_zen();
+                    }
+                    KeyCode::Esc => {
+                        app.interrupt();
+                        return Ok(());
                     }
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.interrupt();
+                        return Ok(());
+                    }
+                    // Throw away this passage and start over with a fresh one
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.restart();
+                    }
+                    KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.toggle_pause();
+                    }
+                    // Typing waits until the test is resumed
+                    _ if app.paused_at.is_some() => {}
+                    // Terminals send Ctrl+Backspace as Ctrl+W or with a modifier. Not Ctrl+H:
+                    // some send that for a plain Backspace
+                    KeyCode::Backspace
+                        if key
+                            .modifiers
+                            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                    {
+                        app.delete_word();
+                    }
+                    KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.delete_word();
+                    }
+                    // Most terminals report Ctrl+Enter as Ctrl+J
+                    KeyCode::Enter | KeyCode::Char('j')
+                        if app.allow_skip && key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        app.skip_word();
+                    }
+                    code if app.is_key_repeat(code) => {}
+                    _ => app.handle_key_event(key.code),
                 }
             }
 
-            // Check if time is up even without keystroke
-            if let Some(start) = app.start_time {
-                if start.elapsed() >= app.test_duration {
-                    app.is_finished = true;
-                }
+            // Between frames, so the bell never lands in the middle of a redraw
+            if app.bell_pending {
+                app.bell_pending = false;
+                execute!(io::stdout(), Print('\u{7}'))?;
             }
 
+            // Warmup can end between keystrokes too
+            app.check_warmup();
+
+            // Check if time is up even without keystroke
+            app.check_time_limit();
+
             if app.is_finished {
+                app.last_result_line = Some(app.get_result_line());
+                // Read once, before this run is added: the personal best, rank and
+                // forecast all compare against the earlier runs
+                let history =
+                    load_history(app.history_format, app.profile.as_deref()).unwrap_or_default();
                 // Save test history
-                if let Err(e) = app.save_history() {
+                if let Err(e) = app.save_history(&history) {
                     eprintln!("Warning: Failed to save test history: {}", e);
                 }
+                if let Err(e) = app.save_bigram_stats() {
+                    eprintln!("Warning: Failed to save bigram timings: {}", e);
+                }
+                if let Err(e) = app.save_word_reviews() {
+                    eprintln!("Warning: Failed to save word reviews: {}", e);
+                }
+                if let Err(e) = app.record_daily_result() {
+                    eprintln!("Warning: Failed to save daily result: {}", e);
+                }
+                if let Err(e) = app.record_playlist_run() {
+                    eprintln!("Warning: Failed to save playlist history: {}", e);
+                }
+                if let Some(path) = &app.export_heatmap
+                    && let Err(e) = app.export_heatmap_svg(path)
+                {
+                    eprintln!("Warning: Failed to export heatmap: {}", e);
+                }
+                if let Some(path) = &app.export_md
+                    && let Err(e) = app.write_markdown_report(path)
+                {
+                    app.deferred_warnings
+                        .push(format!("Failed to export Markdown report: {}", e));
+                }
+                if let Some(path) = &app.report
+                    && let Err(e) = app.write_text_report(path)
+                {
+                    app.deferred_warnings
+                        .push(format!("Failed to write report: {}", e));
+                }
+                if let Some(path) = &app.keylog
+                    && let Err(e) = app.write_keylog(path)
+                {
+                    app.deferred_warnings
+                        .push(format!("Failed to write key log: {}", e));
+                }
+                app.load_history_stats(&history);
+                // A finished playlist waits on its combined results instead
+                if !app.is_playlist_complete() {
+                    app.restart_deadline = app.auto_restart.map(|delay| Instant::now() + delay);
+                }
+                // A finished test leaves nothing to resume
+                if let Err(e) = clear_progress(app.profile.as_deref()) {
+                    eprintln!("Warning: Failed to clear saved progress: {}", e);
+                }
                 break;
             }
         }
@@ -1107,34 +5826,195 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
         loop {
             terminal.draw(|f| ui(f, app))?;
 
-            if event::poll(Duration::from_millis(RENDER_INTERVAL_MS))? {
-                if let Event::Key(key) = event::read()? {
-                    if key.kind == KeyEventKind::Press {
-                        match key.code {
-                            KeyCode::Esc => return Ok(()),
-                            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
-                            KeyCode::Enter => {
-                                app.restart();
-                                break; // Return to main typing loop
-                            }
-                            _ => {} // Ignore other keys to prevent accidental dismissal
-                        }
-                    }
-                }
+            if event::poll(Duration::from_millis(RENDER_INTERVAL_MS))?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                // Any key stops the auto-restart countdown
+                app.restart_deadline = None;
+                // Any key stops a replay without doing anything else
+                if app.replay_start.take().is_some() {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(());
+                    }
+                    KeyCode::Char('r') | KeyCode::Char('R') => {
+                        app.replay_start = Some(Instant::now());
+                    }
+                    // The last test of a playlist leads to the combined results first
+                    KeyCode::Enter if app.is_playlist_complete() && !app.show_playlist_summary => {
+                        app.show_playlist_summary = true;
+                    }
+                    KeyCode::Enter => {
+                        app.restart();
+                        break; // Return to main typing loop
+                    }
+                    KeyCode::Char('s') | KeyCode::Char('S') => {
+                        app.show_share_code = !app.show_share_code;
+                    }
+                    KeyCode::Char('d') | KeyCode::Char('D') => {
+                        app.show_char_frequency = !app.show_char_frequency;
+                    }
+                    KeyCode::Char('l') | KeyCode::Char('L') if app.debug => {
+                        app.show_latency = !app.show_latency;
+                    }
+                    KeyCode::Right | KeyCode::Tab => app.summary_tab = app.summary_tab.next(),
+                    KeyCode::Left | KeyCode::BackTab => {
+                        app.summary_tab = app.summary_tab.previous();
+                    }
+                    KeyCode::Char(digit @ '1'..='6') => {
+                        app.summary_tab = SummaryTab::ALL[digit as usize - '1' as usize];
+                    }
+                    _ => {} // Ignore other keys to prevent accidental dismissal
+                }
+            }
+
+            if app
+                .restart_deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                app.restart();
+                break;
+            }
+        }
+    }
+}
+
+fn ui(f: &mut Frame, app: &App) {
+    let area = f.area();
+    if area.width < MIN_TERMINAL_WIDTH || area.height < MIN_TERMINAL_HEIGHT {
+        render_too_small_screen(f, app);
+    } else if app.replay_start.is_some() {
+        render_replay_screen(f, app);
+    } else if app.show_playlist_summary {
+        render_playlist_summary_screen(f, app);
+    } else if app.is_finished && app.show_latency {
+        render_latency_screen(f, app);
+    } else if app.is_finished && app.show_char_frequency {
+        render_char_frequency_screen(f, app);
+    } else if app.is_finished && app.summary_tab != SummaryTab::Overview {
+        render_summary_tab(f, app);
+    } else if app.is_finished {
+        render_summary_screen(f, app);
+    } else {
+        render_typing_screen(f, app);
+    }
+}
+
+fn get_char_style(app: &App, user_chars: &[char], idx: usize) -> Style {
+    let style = get_typed_char_style(app, user_chars, idx);
+    // The pace marker underlines its character, and stands out while it's still ahead
+    if app.get_pacer_position() == Some(idx) {
+        let style = style.add_modifier(Modifier::UNDERLINED);
+        if idx > app.current_position {
+            return style.fg(Color::Magenta);
+        }
+        return style;
+    }
+    style
+}
+
+fn get_typed_char_style(app: &App, user_chars: &[char], idx: usize) -> Style {
+    let target_char = app.target_chars[idx];
+    if idx < user_chars.len() {
+        // Character has been typed
+        let typed_char = user_chars[idx];
+        if typed_char == target_char {
+            if idx < app.correction_attempts.len() && app.correction_attempts[idx] {
+                Style::default().fg(Color::Rgb(255, 165, 0)) // Orange
+            } else {
+                Style::default().fg(Color::Green)
+            }
+        } else {
+            Style::default().fg(Color::Red)
+        }
+    } else if idx < app.current_position {
+        // Auto-skipped leading whitespace - show as dimmed green
+        if target_char == ' ' || target_char == '\t' {
+            Style::default().fg(Color::Rgb(100, 150, 100)) // Dimmed green
+        } else {
+            Style::default().fg(Color::Green) // Should not happen but fallback
+        }
+    } else if idx == app.current_position {
+        app.get_cursor_style()
+    } else if app.focus_word && idx < app.get_focus_end() {
+        Style::default().fg(app.background.muted())
+    } else if app.focus_word {
+        Style::default().fg(app.background.faded())
+    } else {
+        Style::default().fg(app.background.pending())
+    }
+}
+
+fn wrap_line_ranges(chars: &[char], width: usize, max_lines: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    while start < chars.len() && ranges.len() < max_lines && width > 0 {
+        let mut end = (start + width).min(chars.len());
+        if end < chars.len() {
+            // Break after the last space that fits so words stay whole
+            if let Some(space) = chars[start..end].iter().rposition(|&c| c == ' ') {
+                end = start + space + 1;
             }
         }
+        ranges.push((start, end));
+        start = end;
     }
+
+    ranges
 }
 
-fn ui(f: &mut Frame, app: &App) {
-    if app.is_finished {
-        render_summary_screen(f, app);
-    } else {
-        render_typing_screen(f, app);
-    }
+fn line_of_position(line_ranges: &[(usize, usize)], position: usize) -> usize {
+    // The cursor sits past the last line once the passage is done
+    line_ranges
+        .iter()
+        .position(|&(_, end)| position < end)
+        .unwrap_or(line_ranges.len().saturating_sub(1))
+}
+
+fn styled_line(app: &App, user_chars: &[char], (start, end): (usize, usize)) -> Line<'static> {
+    Line::from(
+        (start..end)
+            .map(|i| {
+                Span::styled(
+                    app.target_chars[i].to_string(),
+                    get_char_style(app, user_chars, i),
+                )
+            })
+            .collect::<Vec<Span>>(),
+    )
+}
+
+fn render_too_small_screen(f: &mut Frame, app: &App) {
+    // The test keeps running underneath; this only stands in until there's room again
+    let area = f.area();
+    let message = Paragraph::new(vec![
+        Line::from("Terminal too small"),
+        Line::from(format!(
+            "{}x{}, needs {}x{}",
+            area.width, area.height, MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT
+        )),
+    ])
+    .wrap(ratatui::widgets::Wrap { trim: true })
+    .style(Style::default().fg(app.background.text()))
+    .alignment(ratatui::layout::Alignment::Center);
+    let top = area.height.saturating_sub(2) / 2;
+    f.render_widget(
+        message,
+        ratatui::layout::Rect {
+            y: area.y + top,
+            height: area.height - top,
+            ..area
+        },
+    );
 }
 
 fn render_typing_screen(f: &mut Frame, app: &App) {
+    let graph_height = if app.live_graph { LIVE_GRAPH_HEIGHT } else { 0 };
     let chunks = Layout::default()
         .direction(ratatui::layout::Direction::Vertical)
         .constraints([
@@ -1143,6 +6023,8 @@ fn render_typing_screen(f: &mut Frame, app: &App) {
             Constraint::Min(5),    // Text area (minimalist)
             Constraint::Length(1), // Spacer
             Constraint::Length(1), // Simple stats
+            // Live WPM graph, only given space with --live-graph
+            Constraint::Length(graph_height),
         ])
         .split(f.area());
 
@@ -1154,7 +6036,29 @@ fn render_typing_screen(f: &mut Frame, app: &App) {
         Duration::ZERO
     };
 
-    let timer_text = format!("{:.0}s", remaining.as_secs_f64());
+    // The warmup countdown stays visible since it says when stats start counting
+    let timer_text = if let Some(needed) = app.launch_gate
+        && (app.is_warming_up() || app.start_time.is_none())
+    {
+        format!(
+            "type {} correct in a row to start",
+            needed.saturating_sub(app.current_streak)
+        )
+    } else if app.is_warming_up() {
+        format!("warmup {:.0}s", app.get_warmup_remaining().as_secs_f64())
+    } else if app.in_overtime {
+        "finish the word".to_string()
+    } else if app.learn {
+        // Lessons have no clock; the line shows finger hints instead
+        app.get_learn_hint().unwrap_or_default()
+    } else if app.hide_timer {
+        String::new()
+    } else if app.mode != TestMode::Timed {
+        // No time limit, so count up instead
+        format!("{:.0}s", elapsed.as_secs_f64())
+    } else {
+        format!("{:.0}s", remaining.as_secs_f64())
+    };
     let timer = Paragraph::new(timer_text)
         .style(Style::default().fg(Color::Yellow))
         .alignment(ratatui::layout::Alignment::Center);
@@ -1166,6 +6070,17 @@ fn render_typing_screen(f: &mut Frame, app: &App) {
     
     // Check if we're in code mode (file source)
     let is_code_mode = app.is_code_mode();
+
+    // Newspaper-style columns: text flows down the left column, then the right
+    let column_count = app.get_column_count(chunks[2].width);
+    let text_areas = Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints(vec![
+            Constraint::Ratio(1, column_count as u32);
+            column_count
+        ])
+        .spacing(if column_count > 1 { COLUMN_SPACING } else { 0 })
+        .split(chunks[2]);
     
     if is_code_mode {
         // Multi-line rendering for code
@@ -1174,16 +6089,22 @@ fn render_typing_screen(f: &mut Frame, app: &App) {
         let mut char_idx = 0;
         
         // Find the current line number
-        let mut current_line_number: usize = 0;
-        for i in 0..app.current_position.min(chars.len()) {
-            if chars[i] == '\n' {
-                current_line_number += 1;
-            }
-        }
+        let current_line_number = chars
+            .iter()
+            .take(app.current_position)
+            .filter(|&&c| c == '\n')
+            .count();
         
         // Calculate viewport - show lines around the current position
         let viewport_height = chunks[2].height as usize;
         let start_line = current_line_number.saturating_sub(viewport_height / 3);
+        let total_lines = viewport_height * column_count;
+
+        // Typewriter scroll pads the top so even the first lines sit on the anchored row
+        if app.typewriter_scroll {
+            let padding = (viewport_height / 3).saturating_sub(current_line_number);
+            lines.extend(std::iter::repeat_n(Line::default(), padding));
+        }
         
         let mut line_count = 0;
         
@@ -1199,34 +6120,9 @@ fn render_typing_screen(f: &mut Frame, app: &App) {
         }
         
         // Build lines for display
-        line_count = 0;
-        while char_idx < chars.len() && line_count < viewport_height {
+        while char_idx < chars.len() && lines.len() < total_lines {
             let target_char = chars[char_idx];
-            
-            let style = if char_idx < user_chars.len() {
-                // Character has been typed
-                let typed_char = user_chars[char_idx];
-                if typed_char == target_char {
-                    if char_idx < app.correction_attempts.len() && app.correction_attempts[char_idx] {
-                        Style::default().fg(Color::Rgb(255, 165, 0)) // Orange
-                    } else {
-                        Style::default().fg(Color::Green)
-                    }
-                } else {
-                    Style::default().fg(Color::Red)
-                }
-            } else if char_idx < app.current_position {
-                // Auto-skipped leading whitespace - show as dimmed green
-                if target_char == ' ' || target_char == '\t' {
-                    Style::default().fg(Color::Rgb(100, 150, 100)) // Dimmed green
-                } else {
-                    Style::default().fg(Color::Green) // Should not happen but fallback
-                }
-            } else if char_idx == app.current_position {
-                Style::default().fg(Color::Black).bg(Color::White)
-            } else {
-                Style::default().fg(Color::DarkGray)
-            };
+            let style = get_char_style(app, &user_chars, char_idx);
             
             if target_char == '\n' {
                 // Special handling for newlines - show a visible marker if it's the cursor position
@@ -1235,7 +6131,6 @@ fn render_typing_screen(f: &mut Frame, app: &App) {
                 }
                 lines.push(Line::from(current_line_spans.clone()));
                 current_line_spans.clear();
-                line_count += 1;
             } else {
                 current_line_spans.push(Span::styled(target_char.to_string(), style));
             }
@@ -1247,43 +6142,55 @@ fn render_typing_screen(f: &mut Frame, app: &App) {
         if !current_line_spans.is_empty() {
             lines.push(Line::from(current_line_spans));
         }
-        
-        let text_paragraph = Paragraph::new(lines)
-            .alignment(ratatui::layout::Alignment::Left)
-            .block(Block::default().borders(Borders::NONE));
-        f.render_widget(text_paragraph, chunks[2]);
-    } else {
-        // Single-line rendering for word mode (existing behavior)
-        let mut spans = Vec::new();
-        let visible_chars = VISIBLE_CHAR_LIMIT;
-        let end_pos = visible_chars.min(chars.len());
-
-        for i in 0..end_pos {
-            let target_char = chars[i];
-            let style = if i < user_chars.len() {
-                let typed_char = user_chars[i];
-                if typed_char == target_char {
-                    if i < app.correction_attempts.len() && app.correction_attempts[i] {
-                        Style::default().fg(Color::Rgb(255, 165, 0))
-                    } else {
-                        Style::default().fg(Color::Green)
-                    }
-                } else {
-                    Style::default().fg(Color::Red)
-                }
-            } else if i == app.current_position {
-                Style::default().fg(Color::Black).bg(Color::White)
-            } else {
-                Style::default().fg(Color::DarkGray)
-            };
 
-            spans.push(Span::styled(target_char.to_string(), style));
+        for (column, area) in text_areas.iter().enumerate() {
+            let column_lines: Vec<Line> = lines
+                .iter()
+                .skip(column * viewport_height)
+                .take(viewport_height)
+                .cloned()
+                .collect();
+            let text_paragraph = Paragraph::new(column_lines)
+                .alignment(ratatui::layout::Alignment::Left)
+                .block(Block::default().borders(Borders::NONE));
+            f.render_widget(text_paragraph, *area);
         }
+    } else if app.typewriter_scroll {
+        // Word mode with the current line held at a third of the way down
+        let viewport_height = chunks[2].height as usize;
+        let anchor_row = viewport_height / 3;
+        let line_ranges = wrap_line_ranges(chars, chunks[2].width as usize, usize::MAX);
+        let current_line = line_of_position(&line_ranges, app.current_position);
+
+        let mut lines = vec![Line::default(); anchor_row.saturating_sub(current_line)];
+        let visible_ranges = line_ranges
+            .iter()
+            .skip(current_line.saturating_sub(anchor_row))
+            .take(viewport_height - lines.len());
+        lines.extend(visible_ranges.map(|&range| styled_line(app, &user_chars, range)));
 
-        let text_paragraph = Paragraph::new(Line::from(spans))
-            .wrap(ratatui::widgets::Wrap { trim: true })
-            .alignment(ratatui::layout::Alignment::Left);
+        let text_paragraph = Paragraph::new(lines).alignment(ratatui::layout::Alignment::Left);
         f.render_widget(text_paragraph, chunks[2]);
+    } else {
+        // Word mode: wrap manually so each column knows where its text starts, and scroll
+        // once the cursor is a third of the way down so the line being typed stays in view
+        let column_width = text_areas[0].width as usize;
+        let column_height = text_areas[0].height as usize;
+        let line_ranges = wrap_line_ranges(chars, column_width, usize::MAX);
+        let current_line = line_of_position(&line_ranges, app.current_position);
+        let first_line = current_line.saturating_sub(column_height / 3);
+
+        for (column, area) in text_areas.iter().enumerate() {
+            let column_lines: Vec<Line> = line_ranges
+                .iter()
+                .skip(first_line + column * column_height)
+                .take(column_height)
+                .map(|&range| styled_line(app, &user_chars, range))
+                .collect();
+            let text_paragraph =
+                Paragraph::new(column_lines).alignment(ratatui::layout::Alignment::Left);
+            f.render_widget(text_paragraph, *area);
+        }
     }
 
     // Simple stats line with progress indicator
@@ -1293,84 +6200,770 @@ fn render_typing_screen(f: &mut Frame, app: &App) {
         (app.current_position as f64 / app.target_chars.len() as f64) * 100.0
     };
     
-    let stats_text = if is_code_mode {
+    let wpm_text = if app.net_wpm {
         format!(
-            "WPM: {:.0} | Accuracy: {:.0}% | Progress: {:.0}%",
+            "{:.0}/{:.0} (gross/net)",
             app.get_current_wpm(),
+            app.get_net_wpm()
+        )
+    } else {
+        format!("{:.0}", app.get_current_wpm())
+    };
+    let stats_text = if app.learn {
+        // No speed pressure while learning
+        format!(
+            "Accuracy: {:.0}% | Progress: {:.0}%",
             app.get_accuracy(),
             progress
         )
-    } else {
+    } else if is_code_mode {
         format!(
-            "WPM: {:.0} | Accuracy: {:.0}%",
-            app.get_current_wpm(),
-            app.get_accuracy()
+            "WPM: {} | Accuracy: {:.0}% | Progress: {:.0}%",
+            wpm_text,
+            app.get_accuracy(),
+            progress
         )
+    } else {
+        format!("WPM: {} | Accuracy: {:.0}%", wpm_text, app.get_accuracy())
+    };
+    let mut stats_text = format!("{} | Errors: {}", stats_text, app.errors);
+    // Only passages that have to be finished have a meaningful amount left
+    if matches!(app.mode, TestMode::Accuracy | TestMode::Words) {
+        let left = app.target_chars.len().saturating_sub(app.current_position);
+        stats_text = format!("{} | Left: {}", stats_text, left);
+    }
+    let mut stats_text = format!("{} | Combo: {}", stats_text, app.current_streak);
+    if app.key_filter_ignored {
+        stats_text = format!("{} | Key filter off: too few words", stats_text);
+    }
+    if let Some(reason) = app.passage_fallback {
+        stats_text = format!("{} | Built-in text: {}", stats_text, reason);
+    }
+    // --accuracy-bg tints the whole line with the heatmap's accuracy colors
+    let stats_style = if app.accuracy_bg {
+        Style::default()
+            .fg(Color::Black)
+            .bg(app.get_accuracy_color(app.get_accuracy() / 100.0))
+    } else {
+        Style::default().fg(Color::Cyan)
     };
     let stats = Paragraph::new(stats_text)
-        .style(Style::default().fg(Color::Cyan))
+        .style(stats_style)
         .alignment(ratatui::layout::Alignment::Center);
     f.render_widget(stats, chunks[4]);
+
+    if app.paused_at.is_some() {
+        // Boxed over the middle of the passage so the break is unmistakable
+        let area = chunks[2];
+        let width = area.width.min(PAUSE_OVERLAY_WIDTH);
+        let height = area.height.min(4);
+        let overlay = ratatui::layout::Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let paused = Paragraph::new(vec![Line::from("PAUSED"), Line::from("Ctrl+P to resume")])
+            .style(Style::default().fg(Color::Yellow))
+            .alignment(ratatui::layout::Alignment::Center)
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(ratatui::widgets::Clear, overlay);
+        f.render_widget(paused, overlay);
+    }
+
+    if app.live_graph {
+        // The first sample only arrives a couple of seconds in, so hold the space till then
+        match wpm_chart(app) {
+            Some(chart) => f.render_widget(chart, chunks[5]),
+            None => f.render_widget(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("WPM Performance"),
+                chunks[5],
+            ),
+        }
+    }
 }
 
 fn render_summary_screen(f: &mut Frame, app: &App) {
-    let chunks = Layout::default()
-        .direction(ratatui::layout::Direction::Vertical)
-        .constraints([
-            Constraint::Length(3),  // Title
-            Constraint::Length(8),  // Stats table
-            Constraint::Length(18), // Key analytics (compact keyboard heatmaps)
-            Constraint::Min(6),     // WPM Graph
-            Constraint::Length(2),  // Instructions
-        ])
-        .split(f.area());
-
-    // Title
-    let title = Paragraph::new("Test Complete!")
-        .style(Style::default().fg(Color::Green))
-        .alignment(ratatui::layout::Alignment::Center)
-        .block(Block::default().borders(Borders::ALL));
-    f.render_widget(title, chunks[0]);
+    let accuracy_mode = app.mode == TestMode::Accuracy;
 
     // Stats Table
-    let rows = vec![
+    let mut wpm_rows = vec![
         Row::new(vec![
-            Cell::from("Average WPM"),
+            Cell::from("Average WPM (gross)"),
             Cell::from(format!("{:.1}", app.get_average_wpm())),
         ]),
         Row::new(vec![
-            Cell::from("Peak WPM"),
-            Cell::from(format!(
-                "{:.1}",
-                app.wpm_history.iter().fold(0.0f64, |acc, &x| acc.max(x))
-            )),
+            Cell::from("Net WPM"),
+            Cell::from(format!("{:.1}", app.get_average_net_wpm())),
         ]),
         Row::new(vec![
-            Cell::from("Accuracy"),
-            Cell::from(format!("{:.1}%", app.get_accuracy())),
+            Cell::from("Peak WPM"),
+            Cell::from(format!("{:.1}", app.get_peak_wpm())),
         ]),
+    ];
+    if let Some((consistency, std_dev)) = app.get_consistency() {
+        wpm_rows.push(Row::new(vec![
+            Cell::from("Consistency"),
+            Cell::from(format!("{:.0}% (±{:.1} WPM)", consistency, std_dev)),
+        ]));
+    }
+    let accuracy_row = Row::new(vec![
+        Cell::from("Accuracy"),
+        Cell::from(format!("{:.1}%", app.get_accuracy())),
+    ]);
+
+    // Accuracy mode leads with accuracy and keeps speed secondary; lessons drop it
+    let mut rows = if app.learn {
+        vec![accuracy_row.style(Style::default().fg(Color::Green))]
+    } else if accuracy_mode {
+        let mut rows = vec![accuracy_row.style(Style::default().fg(Color::Green))];
+        rows.extend(wpm_rows);
+        rows
+    } else {
+        let mut rows = wpm_rows;
+        rows.push(accuracy_row);
+        rows
+    };
+    if !app.learn
+        && let Some(burst_wpm) = app.get_burst_wpm(app.burst_window)
+    {
+        rows.push(Row::new(vec![
+            Cell::from("Burst WPM"),
+            Cell::from(format!(
+                "{:.1} (best {}s)",
+                burst_wpm,
+                app.burst_window.as_secs()
+            )),
+        ]));
+    }
+    if let (Some(wpm), Some(pacer_position)) = (app.pacer, app.get_pacer_position()) {
+        let lead = app.current_position as i64 - pacer_position as i64;
+        let (result, style) = if lead < 0 {
+            (
+                format!("Behind {} WPM by {} chars", wpm, -lead),
+                Style::default().fg(Color::Red),
+            )
+        } else {
+            (
+                format!("Beat {} WPM by {} chars", wpm, lead),
+                Style::default().fg(Color::Green),
+            )
+        };
+        rows.push(Row::new(vec![Cell::from("Pacer"), Cell::from(result)]).style(style));
+    }
+    if let Some(reaction) = app.reaction_time {
+        rows.push(Row::new(vec![
+            Cell::from("Reaction"),
+            Cell::from(format!("{}ms", reaction.as_millis())),
+        ]));
+    }
+    if let Some((key, required)) = app.required_key_accuracy {
+        // A key that never came up can't be judged either way
+        let (result, style) = match app.get_key_accuracy(key) {
+            Some(accuracy) if accuracy >= required => (
+                format!("{:.1}% PASSED", accuracy),
+                Style::default().fg(Color::Green),
+            ),
+            Some(accuracy) => (
+                format!("{:.1}% FAILED (needs {}%)", accuracy, required),
+                Style::default().fg(Color::Red),
+            ),
+            None => (
+                "Not typed".to_string(),
+                Style::default().fg(app.background.muted()),
+            ),
+        };
+        rows.push(
+            Row::new(vec![
+                Cell::from(format!("Key '{}' Accuracy", key.escape_default())),
+                Cell::from(result),
+            ])
+            .style(style),
+        );
+    }
+    if app.word_accuracy {
+        let word_accuracy = app
+            .get_word_accuracy()
+            .map_or("-".to_string(), |accuracy| format!("{:.1}%", accuracy));
+        rows.push(Row::new(vec![
+            Cell::from("Word Accuracy"),
+            Cell::from(word_accuracy),
+        ]));
+    }
+    rows.extend(vec![
         Row::new(vec![
             Cell::from("Characters Typed"),
-            Cell::from(format!("{}", app.current_position)),
+            Cell::from(format!("{}", app.get_characters_typed())),
         ]),
         Row::new(vec![
             Cell::from("Errors"),
             Cell::from(format!("{}", app.errors)),
         ]),
+        Row::new(vec![
+            Cell::from("Best Streak"),
+            Cell::from(format!("{} correct in a row", app.best_streak)),
+        ]),
         Row::new(vec![
             Cell::from("Test Duration"),
-            Cell::from(format!("{:.0}s", app.test_duration.as_secs())),
+            Cell::from(format!("{:.0}s", app.get_test_length().as_secs())),
         ]),
-    ];
+        Row::new(vec![
+            Cell::from("Seed"),
+            Cell::from(format!("{}", app.seed)),
+        ]),
+    ]);
+    if accuracy_mode {
+        let missed_words = app.get_missed_words();
+        rows.push(Row::new(vec![
+            Cell::from("Missed Words"),
+            Cell::from(if missed_words.is_empty() {
+                "None".to_string()
+            } else if missed_words.len() > MAX_LISTED_MISSED_WORDS {
+                format!(
+                    "{} (+{} more)",
+                    missed_words[..MAX_LISTED_MISSED_WORDS].join(", "),
+                    missed_words.len() - MAX_LISTED_MISSED_WORDS
+                )
+            } else {
+                missed_words.join(", ")
+            }),
+        ]));
+    }
+    if let Some(breakdown) = app.get_error_breakdown() {
+        rows.push(Row::new(vec![
+            Cell::from("Error Types"),
+            Cell::from(breakdown),
+        ]));
+    }
+    if app.learn {
+        for (hand, label) in [(Hand::Left, "Left Fingers"), (Hand::Right, "Right Fingers")] {
+            let fingers = app.get_finger_stats(hand);
+            if fingers.is_empty() {
+                continue;
+            }
+            // Keys typed per finger; accuracy is only spelled out where it slipped
+            let usage = fingers
+                .iter()
+                .map(|(finger, stats)| match stats.accuracy() {
+                    Some(accuracy) if stats.errors > 0 => {
+                        format!("{} {} ({:.0}%)", finger, stats.attempts, accuracy)
+                    }
+                    _ => format!("{} {}", finger, stats.attempts),
+                })
+                .collect::<Vec<_>>()
+                .join(" · ");
+            rows.push(Row::new(vec![Cell::from(label), Cell::from(usage)]));
+        }
+    }
+    let (left_hand, right_hand) = app.get_hand_stats();
+    rows.push(Row::new(vec![
+        Cell::from("Hand Balance"),
+        Cell::from(app.get_hand_balance_summary()),
+    ]));
+    if let (Some(left_accuracy), Some(right_accuracy)) =
+        (left_hand.accuracy(), right_hand.accuracy())
+    {
+        rows.push(Row::new(vec![
+            Cell::from("Hand Accuracy"),
+            Cell::from(format!(
+                "left {:.0}% · right {:.0}%",
+                left_accuracy, right_accuracy
+            )),
+        ]));
+    }
+    if app.strict_space {
+        rows.push(Row::new(vec![
+            Cell::from("Space Errors"),
+            Cell::from(format!("{}", app.space_errors)),
+        ]));
+    }
+    if app.require_correction || app.error_behavior == ErrorBehavior::Stay || app.strict_space {
+        let most_retried = match app.get_most_retried_char() {
+            Some((ch, retries)) => format!("'{}' ({} retries)", ch.escape_default(), retries),
+            None => "None".to_string(),
+        };
+        rows.push(Row::new(vec![
+            Cell::from("Most Retried"),
+            Cell::from(most_retried),
+        ]));
+    }
+    let common_typos = app.get_common_typos(COMMON_TYPO_COUNT);
+    if !common_typos.is_empty() {
+        let typos = common_typos
+            .iter()
+            .map(|(typed, word, times)| {
+                let plural = if *times == 1 { "" } else { "s" };
+                format!("\"{}\" for \"{}\" ({} time{})", typed, word, times, plural)
+            })
+            .collect::<Vec<_>>()
+            .join(" · ");
+        rows.push(Row::new(vec![
+            Cell::from("Common Typos"),
+            Cell::from(typos),
+        ]));
+    }
+    let slowest_bigrams = app.get_slowest_bigrams();
+    if !slowest_bigrams.is_empty() {
+        let pairs = slowest_bigrams
+            .iter()
+            .take(SUMMARY_BIGRAM_COUNT)
+            .map(|(bigram, stats)| format!("'{}' {:.0} ms", bigram, stats.average_ms()))
+            .collect::<Vec<_>>()
+            .join(" · ");
+        rows.push(Row::new(vec![
+            Cell::from("Slowest Pairs"),
+            Cell::from(pairs),
+        ]));
+    }
+    if let Some(daily) = &app.daily_summary {
+        rows.push(Row::new(vec![
+            Cell::from("Daily"),
+            Cell::from(daily.as_str()),
+        ]));
+    }
+    if let Some(rank) = &app.wpm_rank {
+        rows.push(Row::new(vec![
+            Cell::from("Ranking"),
+            Cell::from(rank.as_str()),
+        ]));
+    }
+    if let Some(forecast) = &app.wpm_forecast {
+        rows.push(Row::new(vec![
+            Cell::from("Forecast"),
+            Cell::from(forecast.as_str()),
+        ]));
+    }
+    if !app.paused_total.is_zero() {
+        rows.push(Row::new(vec![
+            Cell::from("Paused"),
+            Cell::from(format!(
+                "{:.0}s (not counted)",
+                app.paused_total.as_secs_f64()
+            )),
+        ]));
+    }
+    if let Some(needed) = app.launch_gate {
+        rows.push(Row::new(vec![
+            Cell::from("Launch Gate"),
+            Cell::from(format!("{} correct in a row (excluded from stats)", needed)),
+        ]));
+    }
+    if !app.warmup_duration.is_zero() {
+        rows.push(Row::new(vec![
+            Cell::from("Warmup"),
+            Cell::from(format!(
+                "{}s (excluded from stats)",
+                app.warmup_duration.as_secs()
+            )),
+        ]));
+    }
+    if let Some(best) = app.beaten_best
+        && !app.learn
+    {
+        rows.insert(
+            0,
+            Row::new(vec![
+                Cell::from("New personal best!"),
+                Cell::from(format!(
+                    "{:.1} WPM, up from {:.1}",
+                    app.get_average_wpm(),
+                    best
+                )),
+            ])
+            .style(Style::default().fg(Color::Black).bg(Color::Yellow)),
+        );
+    }
+    let stats_table_height = rows.len() as u16 + 2; // Rows plus borders
+
+    // Key analytics shrink to just the key lists without heatmaps
+    let analytics_height = if app.show_heatmaps { 18 } else { 11 };
+    // The share code borrows the graph's space, so it stays while the code is shown
+    let show_graph = app.show_graph || app.show_share_code;
+    let graph_height = if show_graph { 6 } else { 0 };
+
+    // Switch to the condensed layout when the full one wouldn't fit
+    let full_height = 3 + stats_table_height + analytics_height + graph_height + 2;
+    let compact = app.compact || f.area().height < full_height;
+    if compact {
+        rows.extend(compact_key_rows(app));
+    }
 
-    let table = Table::new(
-        rows,
-        [Constraint::Percentage(50), Constraint::Percentage(50)],
+    // Hidden sections get no space; a filler takes up whatever the graph would have
+    let (title_height, results_height, analytics_height, instructions_height) = if compact {
+        (1, rows.len().div_ceil(2) as u16 + 2, 0, 1)
+    } else {
+        (3, stats_table_height, analytics_height, 2)
+    };
+    let graph_constraint = match (show_graph, compact) {
+        (false, _) => Constraint::Min(0),
+        (true, true) => Constraint::Min(5),
+        (true, false) => Constraint::Min(6),
+    };
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(title_height),        // Title
+            Constraint::Length(results_height),      // Stats table
+            Constraint::Length(analytics_height),    // Key analytics (compact keyboard heatmaps)
+            graph_constraint,                        // WPM Graph
+            Constraint::Length(instructions_height), // Instructions
+        ])
+        .split(f.area());
+    let graph_area = chunks[3];
+    let instructions_area = chunks[4];
+
+    // Title, with the tabs for the other views
+    f.render_widget(summary_tab_bar(app, !compact), chunks[0]);
+
+    if compact {
+        // The key rows went into the results table; there's no room for heatmaps
+        render_results_columns(f, app, rows, chunks[1]);
+    } else {
+        let table = Table::new(
+            rows,
+            [Constraint::Percentage(50), Constraint::Percentage(50)],
+        )
+        .block(Block::default().borders(Borders::ALL).title("Results"))
+        .style(Style::default().fg(app.background.text()));
+        f.render_widget(table, chunks[1]);
+        render_key_analytics(f, app, chunks[2]);
+    }
+
+    // WPM Graph, swapped for the share code while it's toggled on
+    if app.show_share_code {
+        let share_text = app
+            .get_share_code()
+            .unwrap_or_else(|e| format!("Could not create share code: {}", e));
+        let share_code = Paragraph::new(share_text)
+            .wrap(ratatui::widgets::Wrap { trim: false })
+            .style(Style::default().fg(app.background.text()))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Share Code (decode with ratatype --decode-share <CODE>)"),
+            );
+        f.render_widget(share_code, graph_area);
+    } else if show_graph && let Some(chart) = wpm_chart(app) {
+        f.render_widget(chart, graph_area);
+    }
+
+    f.render_widget(summary_instructions(app), instructions_area);
+}
+
+fn summary_tab_bar(app: &App, bordered: bool) -> Tabs<'static> {
+    let tabs = Tabs::new(
+        SummaryTab::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, tab)| format!("{} {}", i + 1, tab.title())),
     )
-    .block(Block::default().borders(Borders::ALL).title("Results"))
-    .style(Style::default().fg(Color::White));
-    f.render_widget(table, chunks[1]);
+    .select(app.summary_tab as usize)
+    .style(Style::default().fg(app.background.muted()))
+    .highlight_style(Style::default().fg(Color::Yellow));
+    if !bordered {
+        return tabs;
+    }
+
+    let title_text = match app.playlist_length {
+        Some(length) => format!("Test {} of {} Complete!", app.playlist_runs.len(), length),
+        None if app.learn => "Lesson Complete!".to_string(),
+        None if app.mode == TestMode::Accuracy => "Accuracy Test Complete!".to_string(),
+        None => "Test Complete!".to_string(),
+    };
+    tabs.block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(Line::from(title_text).centered())
+            .title_style(Style::default().fg(Color::Green)),
+    )
+}
+
+fn render_summary_tab(f: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Title and tabs
+            Constraint::Min(0),    // Selected view
+            Constraint::Length(2), // Instructions
+        ])
+        .split(f.area());
+    f.render_widget(summary_tab_bar(app, true), chunks[0]);
+
+    match app.summary_tab {
+        // Drawn by render_summary_screen
+        SummaryTab::Overview => {}
+        SummaryTab::Speed => f.render_widget(speed_table(app), chunks[1]),
+        SummaryTab::Accuracy => f.render_widget(accuracy_table(app), chunks[1]),
+        SummaryTab::Bigrams => f.render_widget(bigram_table(app), chunks[1]),
+        SummaryTab::Errors => render_error_view(f, app, chunks[1]),
+        SummaryTab::Graph => match wpm_chart(app) {
+            Some(chart) => f.render_widget(chart, chunks[1]),
+            None => f.render_widget(
+                Paragraph::new("No WPM samples recorded").block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("WPM Performance"),
+                ),
+                chunks[1],
+            ),
+        },
+    }
+
+    f.render_widget(summary_instructions(app), chunks[2]);
+}
+
+fn bigram_table(app: &App) -> Table<'static> {
+    // This run's letter pairs, slowest first
+    let bigrams = app.get_slowest_bigrams();
+    let rows: Vec<Row> = if bigrams.is_empty() {
+        vec![Row::new(vec!["No letter pairs typed", "-", "-"])]
+    } else {
+        bigrams
+            .into_iter()
+            .map(|(bigram, stats)| {
+                Row::new(vec![
+                    format!("'{}'", bigram),
+                    format!("{:.0}", stats.average_ms()),
+                    stats.count.to_string(),
+                ])
+            })
+            .collect()
+    };
+
+    Table::new(rows, [Constraint::Ratio(1, 3); 3])
+        .header(
+            Row::new(vec!["Pair", "Average (ms)", "Samples"])
+                .style(Style::default().fg(Color::Yellow))
+                .bottom_margin(1),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Letter Pairs (slowest first)"),
+        )
+        .style(Style::default().fg(app.background.text()))
+}
+
+fn render_error_view(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),    // Error types and problem keys
+            Constraint::Length(5), // Missed words
+        ])
+        .split(area);
+    let columns = Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[0]);
+
+    let total: usize = app.error_kinds.values().sum();
+    let kind_rows: Vec<Row> = app
+        .sorted_error_kinds()
+        .iter()
+        .map(|(kind, count)| {
+            Row::new(vec![
+                kind.to_string(),
+                count.to_string(),
+                format!("{:.0}%", *count as f64 / total as f64 * 100.0),
+            ])
+        })
+        .collect();
+    let kind_table = Table::new(kind_rows, [Constraint::Ratio(1, 3); 3])
+        .header(
+            Row::new(vec!["Type", "Count", "Share"])
+                .style(Style::default().fg(Color::Yellow))
+                .bottom_margin(1),
+        )
+        .block(Block::default().borders(Borders::ALL).title("Error Types"))
+        .style(Style::default().fg(app.background.text()));
+    f.render_widget(kind_table, columns[0]);
+
+    let key_rows: Vec<Row> = app
+        .get_most_error_prone_keys(usize::MAX)
+        .into_iter()
+        .map(|(key, errors)| Row::new(vec![format!("'{}'", key), errors.to_string()]))
+        .collect();
+    let key_table = Table::new(key_rows, [Constraint::Ratio(1, 2); 2])
+        .header(
+            Row::new(vec!["Key", "Errors"])
+                .style(Style::default().fg(Color::Yellow))
+                .bottom_margin(1),
+        )
+        .block(Block::default().borders(Borders::ALL).title("Problem Keys"))
+        .style(Style::default().fg(app.background.text()));
+    f.render_widget(key_table, columns[1]);
+
+    let missed_words = app.get_missed_words();
+    let missed_text = if missed_words.is_empty() {
+        "None".to_string()
+    } else {
+        missed_words.join(", ")
+    };
+    let missed = Paragraph::new(missed_text)
+        .wrap(ratatui::widgets::Wrap { trim: true })
+        .style(Style::default().fg(app.background.text()))
+        .block(Block::default().borders(Borders::ALL).title("Missed Words"));
+    f.render_widget(missed, chunks[1]);
+}
+
+fn wpm_chart(app: &App) -> Option<Chart<'_>> {
+    let graph_data_points = app.get_graph_data_points();
+    if graph_data_points.is_empty() {
+        return None;
+    }
+    let max_wpm = graph_data_points
+        .iter()
+        .map(|(_, wpm)| *wpm)
+        .fold(0.0, f64::max)
+        .max(60.0);
+
+    // While typing, the axis grows with the run rather than spanning the whole test
+    let test_duration_secs = if app.is_finished {
+        app.get_test_length()
+    } else {
+        app.get_elapsed_time()
+    }
+    .as_secs_f64();
+
+    let dataset = Dataset::default()
+        .name("WPM")
+        .marker(ratatui::symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(Color::Cyan))
+        .data(graph_data_points);
+
+    let chart = Chart::new(vec![dataset])
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("WPM Performance"),
+        )
+        .x_axis(
+            Axis::default()
+                .title("Time (s)")
+                .style(Style::default().fg(app.background.muted()))
+                .bounds([0.0, test_duration_secs])
+                .labels(vec![
+                    Line::from("0"),
+                    Line::from(format!("{:.0}", test_duration_secs / 2.0)),
+                    Line::from(format!("{:.0}", test_duration_secs)),
+                ]),
+        )
+        .y_axis(
+            Axis::default()
+                .title("WPM")
+                .style(Style::default().fg(app.background.muted()))
+                .bounds([0.0, max_wpm])
+                .labels(vec![
+                    Line::from("0"),
+                    Line::from(format!("{:.0}", max_wpm / 2.0)),
+                    Line::from(format!("{:.0}", max_wpm)),
+                ]),
+        );
+    Some(chart)
+}
+
+fn summary_instructions(app: &App) -> Paragraph<'static> {
+    let next_action = if app.playlist_length.is_none() {
+        "ENTER to restart"
+    } else if app.is_playlist_complete() {
+        "ENTER for playlist results"
+    } else {
+        "ENTER for the next test"
+    };
+    let instructions_text = match app.restart_deadline {
+        Some(deadline) => format!(
+            "Restarting in {}... press any key to stay, or ESC to exit",
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_secs_f64()
+                .ceil()
+        ),
+        None if app.debug => format!(
+            "Press ESC to exit, {}, R to replay, S to share, D for key details or L for latency",
+            next_action
+        ),
+        None => format!(
+            "Press ESC to exit, {}, R to replay, S to share or D for key details",
+            next_action
+        ),
+    };
+    Paragraph::new(instructions_text)
+        .style(Style::default().fg(Color::Yellow))
+        .alignment(ratatui::layout::Alignment::Center)
+}
+
+fn compact_key_rows(app: &App) -> Vec<Row<'static>> {
+    let format_times = |keys: Vec<(char, Duration)>| {
+        let parts: Vec<String> = keys
+            .iter()
+            .map(|(key, time)| format!("'{}' {}ms", key, time.as_millis()))
+            .collect();
+        if parts.is_empty() {
+            "No data".to_string()
+        } else {
+            parts.join(", ")
+        }
+    };
+    let problem_keys: Vec<String> = app
+        .get_most_error_prone_keys(3)
+        .iter()
+        .map(|(key, errors)| format!("'{}' ({})", key, errors))
+        .collect();
+
+    vec![
+        Row::new(vec![
+            Cell::from("Fastest Keys"),
+            Cell::from(format_times(app.get_fastest_keys(3))),
+        ]),
+        Row::new(vec![
+            Cell::from("Slowest Keys"),
+            Cell::from(format_times(app.get_slowest_keys(3))),
+        ]),
+        Row::new(vec![
+            Cell::from("Problem Keys"),
+            Cell::from(if problem_keys.is_empty() {
+                "None".to_string()
+            } else {
+                problem_keys.join(", ")
+            }),
+        ]),
+    ]
+}
+
+fn render_results_columns<'a>(
+    f: &mut Frame,
+    app: &App,
+    mut rows: Vec<Row<'a>>,
+    area: ratatui::layout::Rect,
+) {
+    // Split the results across two side-by-side tables to halve their height
+    let right_rows = rows.split_off(rows.len().div_ceil(2));
+    let columns = Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    let column_table = |column_rows: Vec<Row<'a>>, block: Block<'a>| {
+        Table::new(
+            column_rows,
+            [Constraint::Percentage(45), Constraint::Percentage(55)],
+        )
+        .block(block)
+        .style(Style::default().fg(app.background.text()))
+    };
+    // Open on the inner edges so the two halves read as one table
+    let left_block = Block::default()
+        .borders(Borders::TOP | Borders::BOTTOM | Borders::LEFT)
+        .title("Results");
+    let right_block = Block::default().borders(Borders::TOP | Borders::BOTTOM | Borders::RIGHT);
+    f.render_widget(column_table(rows, left_block), columns[0]);
+    f.render_widget(column_table(right_rows, right_block), columns[1]);
+}
 
+fn render_key_analytics(f: &mut Frame, app: &App, area: ratatui::layout::Rect) {
     // Key Analytics Section
     let key_analytics_chunks = Layout::default()
         .direction(ratatui::layout::Direction::Horizontal)
@@ -1378,59 +6971,85 @@ fn render_summary_screen(f: &mut Frame, app: &App) {
             Constraint::Percentage(50), // Fastest/Slowest keys
             Constraint::Percentage(50), // Most/Least error-prone keys
         ])
-        .split(chunks[2]);
+        .split(area);
+    f.render_widget(speed_table(app), key_analytics_chunks[0]);
+    f.render_widget(accuracy_table(app), key_analytics_chunks[1]);
+}
 
+fn speed_table(app: &App) -> Table<'_> {
     // Fastest and Slowest Keys
     let fastest_keys = app.get_fastest_keys(3);
     let slowest_keys = app.get_slowest_keys(3);
 
+    let key_row = |(key, time): (char, Duration)| {
+        Row::new(vec![
+            Cell::from(format!("'{}'", key)),
+            Cell::from(format!("{}", time.as_millis())),
+            Cell::from(format!("{:.0}", app.get_key_wpm(time))),
+        ])
+    };
+    let no_data_row = || {
+        Row::new(vec![
+            Cell::from("No data"),
+            Cell::from("-"),
+            Cell::from("-"),
+        ])
+    };
+
     let mut speed_rows = vec![Row::new(vec![
         Cell::from("Fastest Keys"),
         Cell::from("Time (ms)"),
+        Cell::from("WPM"),
     ])];
     if fastest_keys.is_empty() {
-        speed_rows.push(Row::new(vec![Cell::from("No data"), Cell::from("-")]));
+        speed_rows.push(no_data_row());
     } else {
-        for (key, time) in fastest_keys {
-            speed_rows.push(Row::new(vec![
-                Cell::from(format!("'{}'", key)),
-                Cell::from(format!("{}", time.as_millis())),
-            ]));
-        }
+        speed_rows.extend(fastest_keys.into_iter().map(key_row));
     }
     speed_rows.push(Row::new(vec![Cell::from(""), Cell::from("")])); // Spacer
     speed_rows.push(Row::new(vec![
         Cell::from("Slowest Keys"),
         Cell::from("Time (ms)"),
+        Cell::from("WPM"),
     ]));
     if slowest_keys.is_empty() {
-        speed_rows.push(Row::new(vec![Cell::from("No data"), Cell::from("-")]));
+        speed_rows.push(no_data_row());
     } else {
-        for (key, time) in slowest_keys {
-            speed_rows.push(Row::new(vec![
-                Cell::from(format!("'{}'", key)),
-                Cell::from(format!("{}", time.as_millis())),
-            ]));
-        }
+        speed_rows.extend(slowest_keys.into_iter().map(key_row));
     }
 
     // Add speed heatmap to the table
-    speed_rows.push(Row::new(vec![Cell::from(""), Cell::from("")])); // Spacer
-    speed_rows.push(Row::new(vec![Cell::from("Speed Heatmap:"), Cell::from("")]));
+    let heatmap_kind = if app.physical_heatmap {
+        " (physical keys)"
+    } else {
+        ""
+    };
+    if app.show_heatmaps {
+        speed_rows.push(Row::new(vec![Cell::from(""), Cell::from("")])); // Spacer
+        speed_rows.push(Row::new(vec![
+            Cell::from(format!("Speed Heatmap{}:", heatmap_kind)),
+            Cell::from(""),
+        ]));
 
-    let speed_keyboard_lines = app.render_speed_keyboard();
-    for line in speed_keyboard_lines {
-        speed_rows.push(Row::new(vec![Cell::from(line), Cell::from("")]));
+        let speed_keyboard_lines = app.render_speed_keyboard();
+        for line in speed_keyboard_lines {
+            speed_rows.push(Row::new(vec![Cell::from(line), Cell::from("")]));
+        }
     }
 
-    let speed_table = Table::new(
+    Table::new(
         speed_rows,
-        [Constraint::Percentage(60), Constraint::Percentage(40)],
+        [
+            Constraint::Percentage(60),
+            Constraint::Percentage(25),
+            Constraint::Percentage(15),
+        ],
     )
     .block(Block::default().borders(Borders::ALL).title("Key Speed"))
-    .style(Style::default().fg(Color::White));
-    f.render_widget(speed_table, key_analytics_chunks[0]);
+    .style(Style::default().fg(app.background.text()))
+}
 
+fn accuracy_table(app: &App) -> Table<'_> {
     // Most Error-Prone and Most Accurate Keys
     let error_prone_keys = app.get_most_error_prone_keys(3);
     let accurate_keys = app.get_most_accurate_keys(3);
@@ -1458,44 +7077,252 @@ fn render_summary_screen(f: &mut Frame, app: &App) {
     }
 
     // Add accuracy heatmap to the table
-    accuracy_rows.push(Row::new(vec![Cell::from(""), Cell::from("")])); // Spacer
-    accuracy_rows.push(Row::new(vec![
-        Cell::from("Accuracy Heatmap:"),
-        Cell::from(""),
-    ]));
+    let heatmap_kind = if app.physical_heatmap {
+        " (physical keys)"
+    } else {
+        ""
+    };
+    if app.show_heatmaps {
+        accuracy_rows.push(Row::new(vec![Cell::from(""), Cell::from("")])); // Spacer
+        accuracy_rows.push(Row::new(vec![
+            Cell::from(format!("Accuracy Heatmap{}:", heatmap_kind)),
+            Cell::from(""),
+        ]));
 
-    let accuracy_keyboard_lines = app.render_accuracy_keyboard();
-    for line in accuracy_keyboard_lines {
-        accuracy_rows.push(Row::new(vec![Cell::from(line), Cell::from("")]));
+        let accuracy_keyboard_lines = app.render_accuracy_keyboard();
+        for line in accuracy_keyboard_lines {
+            accuracy_rows.push(Row::new(vec![Cell::from(line), Cell::from("")]));
+        }
     }
 
-    let accuracy_table = Table::new(
+    Table::new(
         accuracy_rows,
         [Constraint::Percentage(60), Constraint::Percentage(40)],
     )
     .block(Block::default().borders(Borders::ALL).title("Key Accuracy"))
-    .style(Style::default().fg(Color::White));
-    f.render_widget(accuracy_table, key_analytics_chunks[1]);
+    .style(Style::default().fg(app.background.text()))
+}
 
-    // WPM Graph
-    if !app.wpm_data_points.is_empty() {
-        let max_wpm = app
-            .wpm_data_points
-            .iter()
+fn render_menu_screen(f: &mut Frame, menu: &SettingsMenu) {
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),                            // Title
+            Constraint::Length(menu.fields.len() as u16 + 2), // Settings
+            Constraint::Min(0),
+            Constraint::Length(1), // Instructions
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new("ratatype")
+        .style(Style::default().fg(Color::Green))
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    let rows: Vec<Row> = menu
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let row = Row::new(vec![
+                Cell::from(field.label),
+                Cell::from(format!("< {} >", field.value())),
+            ]);
+            if i == menu.selected {
+                row.style(menu.background.highlight())
+            } else {
+                row
+            }
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [Constraint::Percentage(50), Constraint::Percentage(50)],
+    )
+    .block(Block::default().borders(Borders::ALL).title("Settings"))
+    .style(Style::default().fg(menu.background.text()));
+    f.render_widget(table, chunks[1]);
+
+    let instructions = Paragraph::new(
+        "UP/DOWN to choose a setting, LEFT/RIGHT to change it, ENTER to start or ESC to quit",
+    )
+    .style(Style::default().fg(Color::Yellow))
+    .alignment(ratatui::layout::Alignment::Center);
+    f.render_widget(instructions, chunks[3]);
+}
+
+fn render_compare_screen(
+    f: &mut Frame,
+    first: &TestHistory,
+    second: &TestHistory,
+    background: Background,
+) {
+    let key_table_height = COMPARE_KEY_COUNT as u16 + 3;
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),                // Title
+            Constraint::Length(11),               // Stats side by side
+            Constraint::Length(key_table_height), // Slowest keys
+            Constraint::Min(10),                  // Overlaid WPM graphs
+            Constraint::Length(1),                // Instructions
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new(format!(
+        "Run {} vs Run {}",
+        first.timestamp, second.timestamp
+    ))
+    .style(Style::default().fg(Color::Green))
+    .alignment(ratatui::layout::Alignment::Center)
+    .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    // Change is second minus first, so positive means the later run did better
+    // (except for errors)
+    let number_row = |label: &str, a: f64, b: f64, precision: usize, unit: &str| {
+        Row::new(vec![
+            Cell::from(label.to_string()),
+            Cell::from(format!("{:.*}{}", precision, a, unit)),
+            Cell::from(format!("{:.*}{}", precision, b, unit)),
+            Cell::from(format!("{:+.*}{}", precision, b - a, unit)),
+        ])
+    };
+    let text_row = |label: &str, a: String, b: String| {
+        Row::new(vec![
+            Cell::from(label.to_string()),
+            Cell::from(a),
+            Cell::from(b),
+            Cell::from(""),
+        ])
+    };
+    let rows = vec![
+        number_row("Average WPM", first.avg_wpm, second.avg_wpm, 1, ""),
+        number_row("Peak WPM", first.peak_wpm, second.peak_wpm, 1, ""),
+        number_row("Accuracy", first.accuracy, second.accuracy, 1, "%"),
+        number_row("Errors", first.errors as f64, second.errors as f64, 0, ""),
+        number_row(
+            "Characters Typed",
+            first.characters_typed as f64,
+            second.characters_typed as f64,
+            0,
+            "",
+        ),
+        text_row(
+            "Test Duration",
+            format!("{}s", first.duration_seconds),
+            format!("{}s", second.duration_seconds),
+        ),
+        text_row(
+            "Text Source",
+            first.text_source.clone(),
+            second.text_source.clone(),
+        ),
+    ];
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(31),
+            Constraint::Percentage(23),
+            Constraint::Percentage(23),
+            Constraint::Percentage(23),
+        ],
+    )
+    .header(
+        Row::new(vec![
+            Cell::from(""),
+            Cell::from(format!("Run {}", first.timestamp)).style(Style::default().fg(Color::Cyan)),
+            Cell::from(format!("Run {}", second.timestamp))
+                .style(Style::default().fg(Color::Magenta)),
+            Cell::from("Change"),
+        ])
+        .bottom_margin(1),
+    )
+    .block(Block::default().borders(Borders::ALL).title("Results"))
+    .style(Style::default().fg(background.text()));
+    f.render_widget(table, chunks[1]);
+
+    // Slowest keys of each run
+    let key_chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[2]);
+    for (record, area) in [(first, key_chunks[0]), (second, key_chunks[1])] {
+        let mut key_rows = vec![Row::new(vec![
+            Cell::from("Slowest Keys"),
+            Cell::from("Time (ms)"),
+        ])];
+        match &record.slowest_keys {
+            Some(keys) if !keys.is_empty() => {
+                for (key, millis) in keys {
+                    key_rows.push(Row::new(vec![
+                        Cell::from(format!("'{}'", key)),
+                        Cell::from(format!("{}", millis)),
+                    ]));
+                }
+            }
+            Some(_) => key_rows.push(Row::new(vec![Cell::from("No data"), Cell::from("-")])),
+            // Only JSON Lines history keeps per-key timings
+            None => key_rows.push(Row::new(vec![Cell::from("Not recorded"), Cell::from("-")])),
+        }
+        let key_table = Table::new(
+            key_rows,
+            [Constraint::Percentage(60), Constraint::Percentage(40)],
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Run {}", record.timestamp)),
+        )
+        .style(Style::default().fg(background.text()));
+        f.render_widget(key_table, area);
+    }
+
+    // Overlaid WPM graphs
+    let series = [(first, Color::Cyan), (second, Color::Magenta)];
+    let datasets: Vec<Dataset> = series
+        .iter()
+        .filter_map(|(record, color)| {
+            let points = record.wpm_data_points.as_deref()?;
+            Some(
+                Dataset::default()
+                    .name(format!("Run {}", record.timestamp))
+                    .marker(ratatui::symbols::Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(*color))
+                    .data(points),
+            )
+        })
+        .collect();
+
+    if datasets.is_empty() {
+        let note = Paragraph::new("No WPM graphs recorded (JSON Lines history only)")
+            .style(Style::default().fg(background.muted()))
+            .alignment(ratatui::layout::Alignment::Center)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("WPM Performance"),
+            );
+        f.render_widget(note, chunks[3]);
+    } else {
+        let all_points = || {
+            series
+                .iter()
+                .filter_map(|(record, _)| record.wpm_data_points.as_deref())
+                .flatten()
+        };
+        let max_wpm = all_points()
             .map(|(_, wpm)| *wpm)
             .fold(0.0, f64::max)
             .max(60.0);
+        let max_secs = all_points()
+            .map(|(secs, _)| *secs)
+            .fold(0.0, f64::max)
+            .max(first.duration_seconds.max(second.duration_seconds) as f64);
 
-        let test_duration_secs = app.test_duration.as_secs_f64();
-
-        let dataset = Dataset::default()
-            .name("WPM")
-            .marker(ratatui::symbols::Marker::Braille)
-            .graph_type(GraphType::Line)
-            .style(Style::default().fg(Color::Cyan))
-            .data(&app.wpm_data_points);
-
-        let chart = Chart::new(vec![dataset])
+        let chart = Chart::new(datasets)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
@@ -1504,18 +7331,18 @@ fn render_summary_screen(f: &mut Frame, app: &App) {
             .x_axis(
                 Axis::default()
                     .title("Time (s)")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds([0.0, test_duration_secs])
+                    .style(Style::default().fg(background.muted()))
+                    .bounds([0.0, max_secs])
                     .labels(vec![
                         Line::from("0"),
-                        Line::from(format!("{:.0}", test_duration_secs / 2.0)),
-                        Line::from(format!("{:.0}", test_duration_secs)),
+                        Line::from(format!("{:.0}", max_secs / 2.0)),
+                        Line::from(format!("{:.0}", max_secs)),
                     ]),
             )
             .y_axis(
                 Axis::default()
                     .title("WPM")
-                    .style(Style::default().fg(Color::Gray))
+                    .style(Style::default().fg(background.muted()))
                     .bounds([0.0, max_wpm])
                     .labels(vec![
                         Line::from("0"),
@@ -1523,13 +7350,625 @@ fn render_summary_screen(f: &mut Frame, app: &App) {
                         Line::from(format!("{:.0}", max_wpm)),
                     ]),
             );
-
         f.render_widget(chart, chunks[3]);
     }
 
-    // Instructions
-    let instructions = Paragraph::new("Press ESC to exit or ENTER to restart")
+    let instructions = Paragraph::new("Press any key to exit")
+        .style(Style::default().fg(Color::Yellow))
+        .alignment(ratatui::layout::Alignment::Center);
+    f.render_widget(instructions, chunks[4]);
+}
+
+fn render_history_screen(
+    f: &mut Frame,
+    runs: &[TestHistory],
+    offset: usize,
+    background: Background,
+) {
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Totals
+            Constraint::Min(5),    // Past runs
+            Constraint::Length(1), // Instructions
+        ])
+        .split(f.area());
+
+    let totals = if runs.is_empty() {
+        "No tests recorded yet".to_string()
+    } else {
+        let best_wpm = runs.iter().map(|run| run.avg_wpm).fold(0.0, f64::max);
+        let average_accuracy = runs.iter().map(|run| run.accuracy).sum::<f64>() / runs.len() as f64;
+        format!(
+            "Tests: {} · Best WPM: {:.1} · Average Accuracy: {:.1}%",
+            runs.len(),
+            best_wpm,
+            average_accuracy
+        )
+    };
+    let title = Paragraph::new(totals)
+        .style(Style::default().fg(Color::Green))
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title("History"));
+    f.render_widget(title, chunks[0]);
+
+    let rows: Vec<Row> = runs
+        .iter()
+        .skip(offset)
+        .map(|run| {
+            Row::new(vec![
+                Cell::from(run.timestamp.to_string()),
+                Cell::from(format_day(run.timestamp / SECS_PER_DAY as u64)),
+                Cell::from(format!("{}s", run.duration_seconds)),
+                Cell::from(format!("{:.1}", run.avg_wpm)),
+                Cell::from(format!("{:.1}", run.peak_wpm)),
+                Cell::from(format!("{:.1}%", run.accuracy)),
+                Cell::from(run.errors.to_string()),
+                Cell::from(run.mode.clone().unwrap_or_default()),
+                Cell::from(run.text_source.clone()),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(11),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(6),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(8),
+            Constraint::Min(8),
+        ],
+    )
+    .header(
+        Row::new(vec![
+            "Id", "Date", "Duration", "WPM", "Peak", "Accuracy", "Errors", "Mode", "Source",
+        ])
+        .style(Style::default().fg(Color::Yellow))
+        .bottom_margin(1),
+    )
+    .block(Block::default().borders(Borders::ALL).title(format!(
+        "Past Runs (from {} of {})",
+        (offset + 1).min(runs.len()),
+        runs.len()
+    )))
+    .style(Style::default().fg(background.text()));
+    f.render_widget(table, chunks[1]);
+
+    let instructions = Paragraph::new("↑/↓ or PgUp/PgDn to scroll, ESC or Q to exit")
+        .style(Style::default().fg(Color::Yellow))
+        .alignment(ratatui::layout::Alignment::Center);
+    f.render_widget(instructions, chunks[2]);
+}
+
+fn render_playlist_summary_screen(f: &mut Frame, app: &App) {
+    let run_row = |label: String, run: &PlaylistRun| {
+        Row::new(vec![
+            Cell::from(label),
+            Cell::from(format!("{:.1}", run.avg_wpm)),
+            Cell::from(format!("{:.1}", run.peak_wpm)),
+            Cell::from(format!("{:.1}%", run.accuracy)),
+            Cell::from(format!("{}", run.errors)),
+            Cell::from(format!("{}s", run.duration.as_secs())),
+        ])
+    };
+    let mut rows: Vec<Row> = app
+        .playlist_runs
+        .iter()
+        .enumerate()
+        .map(|(i, run)| run_row(format!("Test {}", i + 1), run))
+        .collect();
+    rows.push(Row::new(vec![Cell::from("")])); // Spacer
+    rows.push(
+        run_row("Total".to_string(), &app.get_playlist_totals())
+            .style(Style::default().fg(Color::Green)),
+    );
+    let table_height = rows.len() as u16 + 4; // Rows, header and borders
+
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),            // Title
+            Constraint::Length(table_height), // Per-test and combined results
+            Constraint::Min(0),
+            Constraint::Length(1), // Instructions
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new(format!(
+        "Playlist Complete! ({} tests)",
+        app.playlist_runs.len()
+    ))
+    .style(Style::default().fg(Color::Green))
+    .alignment(ratatui::layout::Alignment::Center)
+    .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    let table = Table::new(rows, [Constraint::Ratio(1, 6); 6])
+        .header(
+            Row::new(vec![
+                "",
+                "Average WPM",
+                "Peak WPM",
+                "Accuracy",
+                "Errors",
+                "Duration",
+            ])
+            .style(Style::default().fg(Color::Yellow))
+            .bottom_margin(1),
+        )
+        .block(Block::default().borders(Borders::ALL).title("Results"))
+        .style(Style::default().fg(app.background.text()));
+    f.render_widget(table, chunks[1]);
+
+    let instructions = Paragraph::new("Press ESC to exit or ENTER to start a new playlist")
+        .style(Style::default().fg(Color::Yellow))
+        .alignment(ratatui::layout::Alignment::Center);
+    f.render_widget(instructions, chunks[3]);
+}
+
+fn render_char_frequency_screen(f: &mut Frame, app: &App) {
+    let rows: Vec<Row> = app
+        .get_char_frequency()
+        .into_iter()
+        .map(|(ch, count, attempts, errors)| {
+            let error_rate = if attempts == 0 {
+                "-".to_string()
+            } else {
+                format!("{:.1}%", errors as f64 / attempts as f64 * 100.0)
+            };
+            let row = Row::new(vec![
+                Cell::from(format!("'{}'", ch.escape_default())),
+                Cell::from(count.to_string()),
+                Cell::from(attempts.to_string()),
+                Cell::from(errors.to_string()),
+                Cell::from(error_rate),
+            ]);
+            if errors > 0 {
+                row.style(Style::default().fg(Color::Red))
+            } else {
+                row
+            }
+        })
+        .collect();
+
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Title
+            Constraint::Min(0),    // Frequency table
+            Constraint::Length(1), // Instructions
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new("Key Details")
+        .style(Style::default().fg(Color::Green))
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    let table = Table::new(rows, [Constraint::Ratio(1, 5); 5])
+        .header(
+            Row::new(vec![
+                "Key",
+                "In Passage",
+                "Attempts",
+                "Errors",
+                "Error Rate",
+            ])
+            .style(Style::default().fg(Color::Yellow))
+            .bottom_margin(1),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Character Frequency (most common first)"),
+        )
+        .style(Style::default().fg(app.background.text()));
+    f.render_widget(table, chunks[1]);
+
+    let instructions = Paragraph::new("Press D to go back to the results or ESC to exit")
+        .style(Style::default().fg(Color::Yellow))
+        .alignment(ratatui::layout::Alignment::Center);
+    f.render_widget(instructions, chunks[2]);
+}
+
+// Counts of samples per bucket, labelled by the bucket's range in milliseconds
+fn latency_histogram(samples: &[Duration], bounds_ms: &[u64]) -> Vec<(String, u64)> {
+    let mut buckets = vec![0u64; bounds_ms.len() + 1];
+    for sample in samples {
+        let ms = sample.as_millis() as u64;
+        let bucket = bounds_ms
+            .iter()
+            .position(|&bound| ms < bound)
+            .unwrap_or(bounds_ms.len());
+        buckets[bucket] += 1;
+    }
+
+    let mut lower = 0;
+    let mut labels: Vec<String> = bounds_ms
+        .iter()
+        .map(|&bound| {
+            let label = format!("{}-{}", lower, bound);
+            lower = bound;
+            label
+        })
+        .collect();
+    labels.push(format!("{}+", lower));
+    labels.into_iter().zip(buckets).collect()
+}
+
+fn render_latency_screen(f: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),      // Title
+            Constraint::Percentage(50), // Input to frame
+            Constraint::Min(0),         // Keystroke intervals
+            Constraint::Length(1),      // Instructions
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new("Input Latency")
+        .style(Style::default().fg(Color::Green))
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    // Slow frames point at the terminal or connection; slow intervals are the typist
+    let charts = [
+        (
+            "Key read to frame drawn (ms)",
+            &app.frame_latencies,
+            &[5, 10, 20, 50, 100][..],
+            chunks[1],
+        ),
+        (
+            "Time between keystrokes (ms)",
+            &app.key_intervals,
+            &[50, 100, 150, 200, 300, 500][..],
+            chunks[2],
+        ),
+    ];
+    for (label, samples, bounds_ms, area) in charts {
+        let histogram = latency_histogram(samples, bounds_ms);
+        let data: Vec<(&str, u64)> = histogram
+            .iter()
+            .map(|(bucket, count)| (bucket.as_str(), *count))
+            .collect();
+        let median = {
+            let mut sorted = samples.clone();
+            sorted.sort();
+            sorted.get(sorted.len() / 2).copied()
+        };
+        let title = match median {
+            Some(median) => format!(
+                "{} · {} samples · median {}ms",
+                label,
+                samples.len(),
+                median.as_millis()
+            ),
+            None => format!("{} · no samples", label),
+        };
+        let chart = BarChart::default()
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .data(&data)
+            .bar_width(9)
+            .bar_gap(2)
+            .bar_style(Style::default().fg(Color::Cyan))
+            .value_style(Style::default().fg(Color::Black).bg(Color::Cyan));
+        f.render_widget(chart, area);
+    }
+
+    let instructions = Paragraph::new("Press L to go back to the results or ESC to exit")
         .style(Style::default().fg(Color::Yellow))
         .alignment(ratatui::layout::Alignment::Center);
+    f.render_widget(instructions, chunks[3]);
+}
+
+fn render_replay_screen(f: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([
+            Constraint::Length(1), // Playback clock
+            Constraint::Length(1), // Spacer
+            Constraint::Min(5),    // Passage
+            Constraint::Length(1), // Spacer
+            Constraint::Length(1), // Instructions
+        ])
+        .split(f.area());
+
+    let playback = app
+        .replay_start
+        .map_or(Duration::ZERO, |start| start.elapsed());
+    let total = app.typed_times.last().copied().unwrap_or_default();
+    let clock = Paragraph::new(format!(
+        "replay {:.0}s / {:.0}s",
+        playback.min(total).as_secs_f64(),
+        total.as_secs_f64()
+    ))
+    .style(Style::default().fg(Color::Yellow))
+    .alignment(ratatui::layout::Alignment::Center);
+    f.render_widget(clock, chunks[0]);
+
+    // Color each character by how long it took relative to the typical keystroke
+    let delays = app.get_replay_delays();
+    let mut sorted_delays: Vec<Duration> =
+        delays[app.stats_start_position.min(delays.len())..].to_vec();
+    sorted_delays.sort();
+    let median = sorted_delays
+        .get(sorted_delays.len() / 2)
+        .copied()
+        .unwrap_or_default();
+    let heat_color = |delay: Duration| {
+        if delay <= median * 3 / 2 {
+            Color::Green
+        } else if delay <= median * 3 {
+            Color::Yellow
+        } else {
+            Color::Red
+        }
+    };
+
+    // Only what was actually typed, with the playback position kept in view
+    let typed_chars = &app.target_chars[..app.typed_times.len()];
+    let played = app.typed_times.partition_point(|&time| time <= playback);
+    let viewport_height = chunks[2].height as usize;
+    let line_ranges = wrap_line_ranges(typed_chars, chunks[2].width as usize, usize::MAX);
+    let current_line = line_ranges
+        .iter()
+        .position(|&(_, end)| played < end)
+        .unwrap_or(line_ranges.len().saturating_sub(1));
+    let lines: Vec<Line> = line_ranges
+        .iter()
+        .skip(current_line.saturating_sub(viewport_height / 3))
+        .take(viewport_height)
+        .map(|&(start, end)| {
+            Line::from(
+                (start..end)
+                    .map(|i| {
+                        // Newlines would break the wrapped layout, so show them as a marker
+                        let symbol = match typed_chars[i] {
+                            '\n' => "⏎".to_string(),
+                            ch => ch.to_string(),
+                        };
+                        let style = if i < app.stats_start_position || i >= played {
+                            Style::default().fg(app.background.pending())
+                        } else {
+                            Style::default().fg(heat_color(delays[i]))
+                        };
+                        Span::styled(symbol, style)
+                    })
+                    .collect::<Vec<Span>>(),
+            )
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines), chunks[2]);
+
+    let instructions =
+        Paragraph::new("Green: quick · Yellow: slower · Red: hesitated · press any key to stop")
+            .style(Style::default().fg(Color::Yellow))
+            .alignment(ratatui::layout::Alignment::Center);
     f.render_widget(instructions, chunks[4]);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app(args: &[&str]) -> App {
+        let mut argv = vec!["ratatype", "--seed", "7"];
+        argv.extend(args);
+        App::new(&Args::parse_from(argv))
+    }
+
+    fn word_list(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    fn has_adjacent_repeat(text: &str) -> bool {
+        let words: Vec<&str> = text.split(' ').collect();
+        words.windows(2).any(|pair| pair[0] == pair[1])
+    }
+
+    #[test]
+    fn generated_words_never_repeat_back_to_back() {
+        let mut app = test_app(&[]);
+        let words = word_list(&["alpha", "bravo", "charlie", "delta", "echo", "foxtrot"]);
+        for _ in 0..20 {
+            let text = app.generate_word_text(&words, false);
+            assert!(!has_adjacent_repeat(&text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn no_repeat_window_shrinks_for_tiny_word_lists() {
+        let mut app = test_app(&[]);
+        // Two words leave room for a window of one, so they must alternate
+        let text = app.generate_word_text(&word_list(&["ping", "pong"]), false);
+        assert!(!has_adjacent_repeat(&text), "{}", text);
+        // A single word can't avoid itself, but generating must still finish
+        let text = app.generate_word_text(&word_list(&["solo"]), false);
+        assert!(text.split(' ').all(|word| word == "solo"));
+    }
+
+    #[test]
+    fn bench_report_for_a_known_keylog() {
+        let dir = env::temp_dir().join(format!("ratatype-bench-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let passage = "the quick brown fox jumps over the lazy dog";
+        let passage_path = dir.join("passage.txt");
+        fs::write(&passage_path, passage).unwrap();
+
+        // A steady 150ms per key, with an "x" typed for the "k" in "quick"
+        let mut keylog = format!("{}\n", KEYLOG_HEADER);
+        for (i, target) in passage.chars().enumerate() {
+            let typed = if i == 8 { 'x' } else { target };
+            let elapsed_ms = i * 150;
+            keylog.push_str(&format!(
+                "{},{},{},{},150\n",
+                elapsed_ms,
+                target,
+                typed,
+                typed == target
+            ));
+        }
+        let keylog_path = dir.join("keys.csv");
+        fs::write(&keylog_path, keylog).unwrap();
+
+        // What main makes of --file
+        let mut args = Args::parse_from(["ratatype", "--seed", "7"]);
+        args.text_source = TextSource::Passage(passage_path);
+        let mut app = App::new(&args);
+        app.replay_keylog(&read_keylog(&keylog_path).unwrap())
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // 150ms a key is 80 WPM; the first samples run a little fast
+        assert_eq!(
+            app.get_bench_report(),
+            [
+                "Duration: 30.00s",
+                "Average WPM: 83.31",
+                "Peak WPM: 85.71",
+                "Accuracy: 97.67%",
+                "Characters: 43",
+                "Errors: 1",
+                "Keystrokes: 43",
+                "Best Streak: 34",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn skipped_words_replay_from_the_keylog() {
+        let args = ["--keylog", "keys.csv", "--allow-skip"];
+        let mut app = test_app(&args);
+        let first = app.target_chars[0];
+        app.handle_key_event(KeyCode::Char(first));
+        app.skip_word();
+        let next = app.target_chars[app.current_position];
+        app.handle_key_event(KeyCode::Char(next));
+
+        let mut replayed = test_app(&args);
+        replayed.replay_keylog(&app.keystrokes).unwrap();
+        assert_eq!(replayed.current_position, app.current_position);
+        assert_eq!(replayed.errors, app.errors);
+        assert_eq!(replayed.user_input, app.user_input);
+    }
+
+    #[test]
+    fn tab_over_spaces_is_one_keystroke() {
+        let dir = env::temp_dir().join(format!("ratatype-tab-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tab.rs");
+        fs::write(&path, "a    b\n").unwrap();
+
+        let mut app = test_app(&["-s", path.to_str().unwrap()]);
+        fs::remove_dir_all(&dir).unwrap();
+        app.handle_key_event(KeyCode::Char('a'));
+        app.handle_key_event(KeyCode::Tab);
+
+        assert_eq!(app.current_position, 5);
+        assert_eq!(app.user_input, "a    ");
+        assert_eq!(app.total_keystrokes, 2);
+        assert_eq!(app.key_metrics[&' '].times.len(), 1);
+    }
+
+    #[test]
+    fn typos_are_recorded_when_the_cursor_holds() {
+        for args in [["-c"].as_slice(), ["--error-behavior", "stay"].as_slice()] {
+            let mut app = test_app(args);
+            let word: String = app
+                .target_chars
+                .iter()
+                .take_while(|&&ch| ch != ' ')
+                .collect();
+            let wrong = if word.starts_with('x') { 'y' } else { 'x' };
+            app.handle_key_event(KeyCode::Char(wrong));
+            for ch in word.chars().chain([' ']) {
+                app.handle_key_event(KeyCode::Char(ch));
+            }
+
+            let typo: String = [wrong].into_iter().chain(word.chars().skip(1)).collect();
+            assert_eq!(app.word_typos[&word][&typo], 1, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn sparkline_range_matches_the_scaled_buckets() {
+        // Twice as many samples as blocks, so each block averages a pair
+        let values: Vec<f64> = (0..SPARKLINE_WIDTH * 2).map(|i| i as f64).collect();
+        let (line, low, high) = sparkline(&values).unwrap();
+        assert_eq!(line.chars().count(), SPARKLINE_WIDTH);
+        assert_eq!(low, 0.5);
+        assert_eq!(high, (SPARKLINE_WIDTH * 2) as f64 - 1.5);
+    }
+
+    #[test]
+    fn rank_counts_the_current_run_once() {
+        let run = |wpm: &str| {
+            TestHistory::from_csv_line(&format!("1700000000,30,{},60,95,150,3,false,words,5", wpm))
+                .unwrap()
+        };
+        assert_eq!(rank_wpm(&[], 50.0), "No history to compare");
+        assert_eq!(rank_wpm(&[run("40.00")], 50.0), "Best of your 2 runs");
+        // A run saved rounded down doesn't make the unrounded one tie with itself
+        assert_eq!(rank_wpm(&[run("61.24")], 61.244), "Best of your 2 runs");
+        let history = [run("70.00"), run("60.00"), run("40.00"), run("30.00")];
+        assert_eq!(rank_wpm(&history, 50.0), "Top 60% of your 5 runs");
+    }
+
+    #[test]
+    fn malformed_csv_history_is_moved_aside() {
+        let dir = env::temp_dir().join(format!("ratatype-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.csv");
+        fs::write(&path, "not,a,ratatype,history\n1,2,3,4\n").unwrap();
+
+        let record = TestHistory::from_csv_line("1700000000,30,50,60,95,150,3,false,words,5")
+            .expect("valid row");
+        let backup = record
+            .append_csv(&path)
+            .unwrap()
+            .expect("the old file should be moved");
+
+        let backup_name = backup.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(backup_name.starts_with("history.csv.") && backup_name.ends_with(".bak"));
+        assert_eq!(
+            fs::read_to_string(&backup).unwrap(),
+            "not,a,ratatype,history\n1,2,3,4\n"
+        );
+        let content = fs::read_to_string(&path).unwrap();
+        let mut lines = content.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert!(lines.next().unwrap().starts_with("1700000000,30,50.00,"));
+        assert_eq!(lines.next(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "url")]
+    #[test]
+    fn url_cache_key_is_stable() {
+        // Published FNV-1a test vectors; cached file names depend on these never changing
+        assert_eq!(url_cache_key(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(url_cache_key("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(url_cache_key("foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn key_coverage_swaps_never_repeat_back_to_back() {
+        let mut app = test_app(&["--cover-all-keys"]);
+        // Only "ab" has an "a", so coverage keeps asking for it right after it
+        let words = word_list(&["ab", "b", "bb", "bbb"]);
+        for _ in 0..20 {
+            let text = app.generate_word_text(&words, false);
+            assert!(!has_adjacent_repeat(&text), "{}", text);
+        }
+    }
+}